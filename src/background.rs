@@ -0,0 +1,261 @@
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use image::codecs::hdr::HdrDecoder;
+
+use crate::math::{deg_to_rad, rand_unit};
+use crate::ray::Ray;
+use crate::vec3::*;
+
+// Light arriving from infinitely far away, seen by every ray that escapes
+// the scene
+pub trait Background: Send + Sync {
+    fn color(&self, r: &Ray) -> Color;
+
+    // Density, over solid angle, of random() producing direction v. Only
+    // backgrounds that can be sampled as lights need to implement this and
+    // random
+    fn pdf_value(&self, _v: &Vec3) -> f64 {
+        0.0
+    }
+
+    // Random direction towards the background
+    fn random(&self) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
+}
+
+pub struct SolidBackground {
+    pub color: Color,
+}
+
+impl Background for SolidBackground {
+    fn color(&self, _r: &Ray) -> Color {
+        self.color
+    }
+}
+
+// Vertical blend between two colors, the original sky is white to blue
+pub struct GradientBackground {
+    pub bottom: Color,
+    pub top: Color,
+}
+
+impl Background for GradientBackground {
+    fn color(&self, r: &Ray) -> Color {
+        let unit_dir = normalized(r.dir);
+        let t = 0.5 * (unit_dir.y + 1.0);
+
+        self.bottom * (1.0 - t) + self.top * t
+    }
+}
+
+impl GradientBackground {
+    pub fn sky() -> GradientBackground {
+        GradientBackground {
+            bottom: Color::new(1.0, 1.0, 1.0),
+            top: Color::new(0.5, 0.7, 1.0),
+        }
+    }
+}
+
+// Equirectangular (latitude/longitude) radiance map, with +y up and the
+// image's center column facing -z before rotation. Sampled as a light,
+// choosing pixels by how bright they are and how much of the sphere they
+// cover
+pub struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+    // Rotation about the y axis in radians
+    rotation: f64,
+    intensity: f64,
+    // Chance of sampling each pixel, and running totals of it down the
+    // rows and along each row, for picking a row and then a pixel in it
+    chances: Vec<f64>,
+    row_totals: Vec<f64>,
+    column_totals: Vec<f64>,
+}
+
+impl EnvironmentMap {
+    pub fn new(
+        width: usize,
+        height: usize,
+        pixels: Vec<Color>,
+        rotation_degrees: f64,
+        intensity: f64,
+    ) -> Result<EnvironmentMap, String> {
+        if width == 0 || height == 0 {
+            return Err("environment map has no pixels".to_string());
+        }
+        if pixels.len() != width * height {
+            return Err(format!(
+                "expected {} pixels for a {}x{} environment map, found {}",
+                width * height,
+                width,
+                height,
+                pixels.len()
+            ));
+        }
+
+        // Pixels shrink towards the poles by sin(theta). A map with no
+        // light at all gets no chances and isn't sampled
+        let mut chances: Vec<f64> = pixels
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                let theta = ((i / width) as f64 + 0.5) / height as f64 * PI;
+                luminance(c).max(0.0) * theta.sin()
+            })
+            .collect();
+        let total: f64 = chances.iter().sum();
+        if total > 0.0 {
+            chances.iter_mut().for_each(|c| *c /= total);
+        }
+
+        let mut row_totals = Vec::with_capacity(height);
+        let mut column_totals = Vec::with_capacity(width * height);
+        let mut rows = 0.0;
+        for row in chances.chunks(width) {
+            let mut columns = 0.0;
+            for c in row {
+                columns += c;
+                column_totals.push(columns);
+            }
+            rows += columns;
+            row_totals.push(rows);
+        }
+
+        Ok(EnvironmentMap {
+            width,
+            height,
+            pixels,
+            rotation: deg_to_rad(rotation_degrees),
+            intensity,
+            chances,
+            row_totals,
+            column_totals,
+        })
+    }
+
+    // Loads a Radiance .hdr file
+    pub fn load(
+        path: &Path,
+        rotation_degrees: f64,
+        intensity: f64,
+    ) -> Result<EnvironmentMap, String> {
+        let file = File::open(path)
+            .map_err(|why| format!("could not open {}: {}", path.display(), why))?;
+        let decoder = HdrDecoder::new(BufReader::new(file))
+            .map_err(|why| format!("could not read {}: {}", path.display(), why))?;
+        let meta = decoder.metadata();
+        let pixels = decoder
+            .read_image_hdr()
+            .map_err(|why| format!("could not read {}: {}", path.display(), why))?;
+
+        let pixels = pixels
+            .iter()
+            .map(|p| Color::new(p.0[0] as f64, p.0[1] as f64, p.0[2] as f64))
+            .collect();
+
+        EnvironmentMap::new(
+            meta.width as usize,
+            meta.height as usize,
+            pixels,
+            rotation_degrees,
+            intensity,
+        )
+        .map_err(|why| format!("{}: {}", path.display(), why))
+    }
+
+    fn texel(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    // Bilinear lookup at continuous pixel coordinates, wrapping around
+    // horizontally and clamping at the poles
+    fn sample(&self, x: f64, y: f64) -> Color {
+        let x = x - 0.5;
+        let y = (y - 0.5).clamp(0.0, (self.height - 1) as f64);
+
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let fx = x - x0;
+        let fy = y - y0;
+
+        let w = self.width as i64;
+        let x0 = (x0 as i64).rem_euclid(w) as usize;
+        let x1 = (x0 + 1) % self.width;
+        let y0 = y0 as usize;
+        let y1 = (y0 + 1).min(self.height - 1);
+
+        let top = self.texel(x0, y0) * (1.0 - fx) + self.texel(x1, y0) * fx;
+        let bottom = self.texel(x0, y1) * (1.0 - fx) + self.texel(x1, y1) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+
+    // Map coordinates, from 0 to 1 across and down, of a unit direction
+    fn uv(&self, d: &Vec3) -> (f64, f64) {
+        // Longitude measured from -z towards +x, offset by the map's rotation
+        let phi = d.x.atan2(-d.z) - self.rotation;
+        let theta = d.y.clamp(-1.0, 1.0).acos();
+
+        ((phi / (2.0 * PI) + 0.5).rem_euclid(1.0), theta / PI)
+    }
+}
+
+// Relative brightness of a color as the eye sees it
+fn luminance(c: &Color) -> f64 {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
+
+// First index whose running total passes a random fraction of the last
+fn pick(totals: &[f64]) -> usize {
+    let target = rand_unit() * totals[totals.len() - 1];
+    totals
+        .partition_point(|&total| total <= target)
+        .min(totals.len() - 1)
+}
+
+impl Background for EnvironmentMap {
+    fn color(&self, r: &Ray) -> Color {
+        let (u, v) = self.uv(&normalized(r.dir));
+
+        self.sample(u * self.width as f64, v * self.height as f64) * self.intensity
+    }
+
+    // Pixels are sampled uniformly over their uv, and a pixel at polar
+    // angle theta covers 2 pi^2 sin(theta) / (width * height) steradians
+    fn pdf_value(&self, v: &Vec3) -> f64 {
+        let d = normalized(*v);
+        let sin_theta = (1.0 - d.y * d.y).max(0.0).sqrt();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+
+        let (u, v) = self.uv(&d);
+        let x = ((u * self.width as f64) as usize).min(self.width - 1);
+        let y = ((v * self.height as f64) as usize).min(self.height - 1);
+        let pixels = (self.width * self.height) as f64;
+
+        self.chances[y * self.width + x] * pixels / (2.0 * PI * PI * sin_theta)
+    }
+
+    fn random(&self) -> Vec3 {
+        let y = pick(&self.row_totals);
+        let x = pick(&self.column_totals[y * self.width..(y + 1) * self.width]);
+
+        let u = (x as f64 + rand_unit()) / self.width as f64;
+        let v = (y as f64 + rand_unit()) / self.height as f64;
+        let phi = (u - 0.5) * 2.0 * PI + self.rotation;
+        let theta = v * PI;
+
+        Vec3::new(
+            theta.sin() * phi.sin(),
+            theta.cos(),
+            -theta.sin() * phi.cos(),
+        )
+    }
+}
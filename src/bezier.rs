@@ -0,0 +1,379 @@
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::bvh::BvhNode;
+use crate::hittable::*;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vec3::*;
+
+// Pieces stop being split once their control points are within this
+// fraction of the whole patch's size of the flat quad between their corners
+const FLATNESS: f64 = 0.01;
+// Most times a patch is split in four
+const MAX_DEPTH: usize = 6;
+// Newton steps tried for each piece a ray reaches
+const NEWTON_STEPS: usize = 12;
+
+// Control points of a bicubic patch, in rows along u of four points along v
+pub type ControlPoints = [Point3; 16];
+
+// Reads patches in the format the Utah teapot is commonly shared in: the
+// number of patches, then 16 vertex indices for each counting from 1, then
+// the number of vertices, then x, y and z for each. Numbers can be split by
+// commas or whitespace, and # starts a comment
+pub fn load_patches(path: &Path) -> Result<Vec<ControlPoints>, String> {
+    let source = fs::read_to_string(path)
+        .map_err(|why| format!("could not read {}: {}", path.display(), why))?;
+    let error = |message: String| format!("{}: {}", path.display(), message);
+
+    let mut tokens = source
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or(""))
+        .flat_map(|line| line.split(|c: char| c == ',' || c.is_whitespace()))
+        .filter(|token| !token.is_empty());
+    let mut next = |what: &dyn Fn() -> String| {
+        tokens
+            .next()
+            .ok_or_else(|| error(format!("file ends before {}", what())))
+    };
+    let count = |token: &str, what: &str| {
+        token.parse::<usize>().map_err(|_| {
+            error(format!(
+                "expected the number of {}, found '{}'",
+                what, token
+            ))
+        })
+    };
+
+    let patch_count = count(next(&|| "the number of patches".to_string())?, "patches")?;
+    let mut indices = Vec::with_capacity(patch_count);
+    for patch in 0..patch_count {
+        let mut patch_indices = [0; 16];
+        for index in &mut patch_indices {
+            let token = next(&|| format!("the indices of patch {}", patch + 1))?;
+            *index = token.parse::<usize>().map_err(|_| {
+                error(format!(
+                    "expected a vertex index in patch {}, found '{}'",
+                    patch + 1,
+                    token
+                ))
+            })?;
+        }
+        indices.push(patch_indices);
+    }
+
+    let vertex_count = count(next(&|| "the number of vertices".to_string())?, "vertices")?;
+    let mut vertices = Vec::with_capacity(vertex_count);
+    for vertex in 0..vertex_count {
+        let mut p = [0.0; 3];
+        for coord in &mut p {
+            let token = next(&|| format!("the coordinates of vertex {}", vertex + 1))?;
+            *coord = token.parse::<f64>().map_err(|_| {
+                error(format!(
+                    "expected a coordinate of vertex {}, found '{}'",
+                    vertex + 1,
+                    token
+                ))
+            })?;
+        }
+        vertices.push(Point3::new(p[0], p[1], p[2]));
+    }
+
+    indices
+        .iter()
+        .enumerate()
+        .map(|(patch, patch_indices)| {
+            let mut control = [Point3::new(0.0, 0.0, 0.0); 16];
+            for (point, &index) in control.iter_mut().zip(patch_indices) {
+                if index == 0 || index > vertices.len() {
+                    return Err(error(format!(
+                        "patch {} uses vertex {}, but there are {} vertices",
+                        patch + 1,
+                        index,
+                        vertices.len()
+                    )));
+                }
+                *point = vertices[index - 1];
+            }
+            Ok(control)
+        })
+        .collect()
+}
+
+// Cubic Bernstein polynomials at t, and their derivatives
+fn bernstein(t: f64) -> ([f64; 4], [f64; 4]) {
+    let s = 1.0 - t;
+    (
+        [s * s * s, 3.0 * t * s * s, 3.0 * t * t * s, t * t * t],
+        [
+            -3.0 * s * s,
+            3.0 * s * s - 6.0 * t * s,
+            6.0 * t * s - 3.0 * t * t,
+            3.0 * t * t,
+        ],
+    )
+}
+
+// Point on the patch and its partial derivatives along u and v
+fn evaluate(control: &ControlPoints, u: f64, v: f64) -> (Point3, Vec3, Vec3) {
+    let (bu, du) = bernstein(u);
+    let (bv, dv) = bernstein(v);
+    let zero = Vec3::new(0.0, 0.0, 0.0);
+    let (mut p, mut pu, mut pv) = (zero, zero, zero);
+    for i in 0..4 {
+        for j in 0..4 {
+            let c = control[i * 4 + j];
+            p += c * (bu[i] * bv[j]);
+            pu += c * (du[i] * bv[j]);
+            pv += c * (bu[i] * dv[j]);
+        }
+    }
+    (p, pu, pv)
+}
+
+// Splits a cubic curve in half with de Casteljau's algorithm
+fn split_curve(p: [Point3; 4]) -> ([Point3; 4], [Point3; 4]) {
+    let mid = |a: Point3, b: Point3| (a + b) * 0.5;
+    let (p01, p12, p23) = (mid(p[0], p[1]), mid(p[1], p[2]), mid(p[2], p[3]));
+    let (p012, p123) = (mid(p01, p12), mid(p12, p23));
+    let center = mid(p012, p123);
+    ([p[0], p01, p012, center], [center, p123, p23, p[3]])
+}
+
+// Halves of a patch split across u, at the middle of its rows
+fn split_u(control: &ControlPoints) -> (ControlPoints, ControlPoints) {
+    let (mut low, mut high) = (*control, *control);
+    for j in 0..4 {
+        let column = [0, 1, 2, 3].map(|i| control[i * 4 + j]);
+        let (a, b) = split_curve(column);
+        for i in 0..4 {
+            low[i * 4 + j] = a[i];
+            high[i * 4 + j] = b[i];
+        }
+    }
+    (low, high)
+}
+
+// Halves of a patch split across v, at the middle of its columns
+fn split_v(control: &ControlPoints) -> (ControlPoints, ControlPoints) {
+    let (mut low, mut high) = (*control, *control);
+    for i in 0..4 {
+        let row = [0, 1, 2, 3].map(|j| control[i * 4 + j]);
+        let (a, b) = split_curve(row);
+        low[i * 4..i * 4 + 4].copy_from_slice(&a);
+        high[i * 4..i * 4 + 4].copy_from_slice(&b);
+    }
+    (low, high)
+}
+
+// Furthest any control point is from the bilinear quad between the corners
+fn flatness(control: &ControlPoints) -> f64 {
+    let (c00, c03, c30, c33) = (control[0], control[3], control[12], control[15]);
+    (0..16)
+        .map(|k| {
+            let (s, t) = ((k / 4) as f64 / 3.0, (k % 4) as f64 / 3.0);
+            let bilinear =
+                (c00 * (1.0 - t) + c03 * t) * (1.0 - s) + (c30 * (1.0 - t) + c33 * t) * s;
+            (control[k] - bilinear).length()
+        })
+        .fold(0.0, f64::max)
+}
+
+fn control_box(control: &ControlPoints) -> Aabb {
+    control
+        .iter()
+        .fold(Aabb::empty(), |bbox, p| bbox.expand(*p))
+        .padded()
+}
+
+// Part of a patch over a range of its parameters, nearly flat, which rays
+// are intersected with by Newton's method starting from its middle, or
+// failing that from its corner nearest the ray
+struct PatchPiece {
+    control: Arc<ControlPoints>,
+    mat: Arc<dyn Material>,
+    u_range: (f64, f64),
+    v_range: (f64, f64),
+    // Holds the piece, since a patch lies inside its control points' hull
+    bbox: Aabb,
+    // Size of the whole patch, which its derivatives scale with
+    size: f64,
+    // Largest error in the surface equation that counts as a hit
+    tolerance: f64,
+}
+
+impl PatchPiece {
+    // Solves S(u, v) = o + t d for u, v and t from a starting guess,
+    // returning the solution if it lands on this piece between t_min and
+    // t_max
+    fn newton(
+        &self,
+        r: &Ray,
+        t_min: f64,
+        t_max: f64,
+        (mut u, mut v, mut t): (f64, f64, f64),
+    ) -> Option<(f64, f64, f64)> {
+        // The Jacobian's determinant scales with both derivatives and the
+        // ray's direction, so judge it against those
+        let d = -r.dir;
+        let singular = 1e-12 * self.size * self.size * r.dir.length();
+
+        let mut converged = false;
+        for _ in 0..NEWTON_STEPS {
+            let (p, pu, pv) = evaluate(&self.control, u, v);
+            let f = p - r.at(t);
+            if f.length() < self.tolerance {
+                converged = true;
+                break;
+            }
+
+            // Jacobian columns are pu, pv and -d, solved with Cramer's rule
+            let det = dot(&pu, &cross(&pv, &d));
+            if det.abs() < singular {
+                return None;
+            }
+            let x = -f;
+            u += dot(&x, &cross(&pv, &d)) / det;
+            v += dot(&pu, &cross(&x, &d)) / det;
+            t += dot(&pu, &cross(&pv, &x)) / det;
+        }
+
+        // Steps can wander off to a neighbouring piece, which finds that
+        // hit itself
+        let slack = 1e-6;
+        if !converged
+            || t < t_min
+            || t > t_max
+            || u < self.u_range.0 - slack
+            || u > self.u_range.1 + slack
+            || v < self.v_range.0 - slack
+            || v > self.v_range.1 + slack
+        {
+            return None;
+        }
+        Some((u, v, t))
+    }
+
+    // Corner of the piece closest to the ray, with where along the ray it
+    // comes closest, clamped to the given interval
+    fn nearest_corner(&self, r: &Ray, t_enter: f64, t_exit: f64) -> (f64, f64, f64) {
+        let (u0, u1) = self.u_range;
+        let (v0, v1) = self.v_range;
+        let length_squared = r.dir.length_sq();
+
+        [(u0, v0), (u0, v1), (u1, v0), (u1, v1)]
+            .into_iter()
+            .map(|(u, v)| {
+                let (p, _, _) = evaluate(&self.control, u, v);
+                let t = (dot(&(p - r.origin), &r.dir) / length_squared).clamp(t_enter, t_exit);
+                (u, v, t, (p - r.at(t)).length_sq())
+            })
+            .min_by(|a, b| a.3.total_cmp(&b.3))
+            .map(|(u, v, t, _)| (u, v, t))
+            .unwrap()
+    }
+}
+
+impl Hittable for PatchPiece {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let Some((t_enter, t_exit)) = self.bbox.interval(r, t_min, t_max) else {
+            return false;
+        };
+
+        let middle = (
+            0.5 * (self.u_range.0 + self.u_range.1),
+            0.5 * (self.v_range.0 + self.v_range.1),
+            0.5 * (t_enter + t_exit),
+        );
+        let Some((u, v, t)) = self
+            .newton(r, t_min, t_max, middle)
+            .or_else(|| self.newton(r, t_min, t_max, self.nearest_corner(r, t_enter, t_exit)))
+        else {
+            return false;
+        };
+        let (u, v) = (u.clamp(0.0, 1.0), v.clamp(0.0, 1.0));
+
+        let (p, pu, pv) = evaluate(&self.control, u, v);
+        let mut n = cross(&pu, &pv);
+        if n.near_zero() {
+            // Edges collapsed to a point, like the top of a lid, have no
+            // tangent along them. The normal just inside the patch is the
+            // limit there
+            let (u_in, v_in) = (0.5 + (u - 0.5) * 0.999, 0.5 + (v - 0.5) * 0.999);
+            let (_, pu, pv) = evaluate(&self.control, u_in, v_in);
+            n = cross(&pu, &pv);
+            if n.near_zero() {
+                return false;
+            }
+        }
+
+        *rec = HitRecord::new(p, t, r, &normalized(n), self.mat.clone());
+        (rec.u, rec.v) = (u, v);
+        true
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        *output_box = self.bbox;
+        true
+    }
+}
+
+// Bicubic Bézier patch, intersected directly rather than as triangles. The
+// patch is split into pieces until each is nearly flat, the pieces go in a
+// BVH, and the ray is solved for on each piece it reaches. Normals come
+// from the surface's derivatives, facing along u x v, and uvs are the
+// patch parameters
+pub struct BezierPatch {
+    pieces: BvhNode,
+}
+
+impl BezierPatch {
+    pub fn new(control: ControlPoints, mat: Arc<dyn Material>) -> BezierPatch {
+        let bbox = control_box(&control);
+        let size = (bbox.max - bbox.min).length();
+        let tolerance = 1e-7 * size.max(1e-6);
+        let shared = Arc::new(control);
+
+        let mut pieces = HittableList::new();
+        let mut pending = vec![(control, (0.0, 1.0), (0.0, 1.0), 0)];
+        while let Some((piece, u_range, v_range, depth)) = pending.pop() {
+            if depth < MAX_DEPTH && flatness(&piece) > FLATNESS * size {
+                let (u_mid, v_mid) = (0.5 * (u_range.0 + u_range.1), 0.5 * (v_range.0 + v_range.1));
+                let (low, high) = split_u(&piece);
+                for (half, u_half) in [(low, (u_range.0, u_mid)), (high, (u_mid, u_range.1))] {
+                    let (a, b) = split_v(&half);
+                    pending.push((a, u_half, (v_range.0, v_mid), depth + 1));
+                    pending.push((b, u_half, (v_mid, v_range.1), depth + 1));
+                }
+                continue;
+            }
+
+            pieces.add(Arc::new(PatchPiece {
+                control: shared.clone(),
+                mat: mat.clone(),
+                u_range,
+                v_range,
+                bbox: control_box(&piece),
+                size,
+                tolerance,
+            }));
+        }
+
+        BezierPatch {
+            pieces: BvhNode::new(&pieces),
+        }
+    }
+}
+
+impl Hittable for BezierPatch {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        self.pieces.hit(r, t_min, t_max, rec)
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        self.pieces.bounding_box(output_box)
+    }
+}
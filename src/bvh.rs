@@ -0,0 +1,278 @@
+use std::sync::Arc;
+
+use crate::aabb::{surrounding_box, Aabb};
+use crate::hittable::*;
+use crate::ray::Ray;
+
+// Number of buckets centroids are binned into when evaluating splits
+const SAH_BUCKETS: usize = 16;
+
+// Leaves at or below this size are kept if splitting doesn't pay off
+const MAX_LEAF_SIZE: usize = 4;
+
+// Relative cost of a ray/box test compared to a ray/primitive test
+const TRAVERSAL_COST: f64 = 0.125;
+
+pub struct BvhNode {
+    left: Arc<dyn Hittable>,
+    // None for a node holding just one object
+    right: Option<Arc<dyn Hittable>>,
+    // None only at the root of a tree holding unbounded objects, or no
+    // objects at all
+    bbox: Option<Aabb>,
+}
+
+struct BuildEntry {
+    object: Arc<dyn Hittable>,
+    bbox: Aabb,
+    centroid: crate::vec3::Point3,
+}
+
+impl BvhNode {
+    // Objects without a bounding box, like infinite planes, can't be placed
+    // in the tree, so they're kept in a list next to it that every ray is
+    // tested against. An empty list gives a tree nothing hits
+    pub fn new(list: &HittableList) -> BvhNode {
+        let mut entries = Vec::new();
+        let mut unbounded = HittableList::new();
+        for object in &list.objects {
+            let mut bbox = Aabb::empty();
+            if object.bounding_box(&mut bbox) {
+                entries.push(BuildEntry {
+                    object: object.clone(),
+                    bbox,
+                    centroid: bbox.centroid(),
+                });
+            } else {
+                unbounded.add(object.clone());
+            }
+        }
+
+        if unbounded.objects.is_empty() && !entries.is_empty() {
+            return BvhNode::build(&mut entries);
+        }
+
+        let unbounded: Arc<dyn Hittable> = Arc::new(unbounded);
+        if entries.is_empty() {
+            return BvhNode {
+                left: unbounded,
+                right: None,
+                bbox: None,
+            };
+        }
+
+        BvhNode {
+            left: Arc::new(BvhNode::build(&mut entries)),
+            right: Some(unbounded),
+            bbox: None,
+        }
+    }
+
+    fn build(entries: &mut [BuildEntry]) -> BvhNode {
+        let bbox = entries
+            .iter()
+            .fold(Aabb::empty(), |acc, e| surrounding_box(&acc, &e.bbox));
+
+        if entries.len() == 1 {
+            return BvhNode {
+                left: entries[0].object.clone(),
+                right: None,
+                bbox: Some(bbox),
+            };
+        }
+
+        if entries.len() == 2 {
+            return BvhNode {
+                left: entries[0].object.clone(),
+                right: Some(entries[1].object.clone()),
+                bbox: Some(bbox),
+            };
+        }
+
+        let centroid_box = entries
+            .iter()
+            .fold(Aabb::empty(), |acc, e| acc.expand(e.centroid));
+        let axis = centroid_box.longest_axis();
+        let extent = centroid_box.max[axis] - centroid_box.min[axis];
+
+        let mid = if extent <= 0.0 {
+            // All centroids coincide, nothing for the heuristic to work with
+            entries.len() / 2
+        } else {
+            match BvhNode::sah_split(entries, &bbox, &centroid_box, axis) {
+                Some(mid) => mid,
+                None => return BvhNode::leaf(entries, bbox),
+            }
+        };
+
+        let (left_entries, right_entries) = entries.split_at_mut(mid);
+
+        BvhNode {
+            left: BvhNode::child(left_entries),
+            right: Some(BvhNode::child(right_entries)),
+            bbox: Some(bbox),
+        }
+    }
+
+    // Partitions entries along axis at the cheapest bucket boundary and returns
+    // the partition index, or None if a leaf would be cheaper than any split
+    fn sah_split(
+        entries: &mut [BuildEntry],
+        bbox: &Aabb,
+        centroid_box: &Aabb,
+        axis: usize,
+    ) -> Option<usize> {
+        let min = centroid_box.min[axis];
+        let extent = centroid_box.max[axis] - min;
+        let bucket_of = |e: &BuildEntry| {
+            let b = (SAH_BUCKETS as f64 * (e.centroid[axis] - min) / extent) as usize;
+            b.min(SAH_BUCKETS - 1)
+        };
+
+        let mut counts = [0usize; SAH_BUCKETS];
+        let mut bounds = [Aabb::empty(); SAH_BUCKETS];
+        for e in entries.iter() {
+            let b = bucket_of(e);
+            counts[b] += 1;
+            bounds[b] = surrounding_box(&bounds[b], &e.bbox);
+        }
+
+        // Cost of splitting after bucket i, relative to the parent's area
+        let parent_area = bbox.surface_area();
+        let mut best_cost = f64::INFINITY;
+        let mut best_bucket = 0;
+
+        for i in 0..SAH_BUCKETS - 1 {
+            let (mut box0, mut box1) = (Aabb::empty(), Aabb::empty());
+            let (mut count0, mut count1) = (0, 0);
+
+            for j in 0..=i {
+                box0 = surrounding_box(&box0, &bounds[j]);
+                count0 += counts[j];
+            }
+            for j in i + 1..SAH_BUCKETS {
+                box1 = surrounding_box(&box1, &bounds[j]);
+                count1 += counts[j];
+            }
+
+            if count0 == 0 || count1 == 0 {
+                continue;
+            }
+
+            let cost = TRAVERSAL_COST
+                + (count0 as f64 * box0.surface_area() + count1 as f64 * box1.surface_area())
+                    / parent_area;
+            if cost < best_cost {
+                best_cost = cost;
+                best_bucket = i;
+            }
+        }
+
+        if entries.len() <= MAX_LEAF_SIZE && best_cost >= entries.len() as f64 {
+            return None;
+        }
+
+        // Partition in place so everything up to mid is in the left child
+        let mut mid = 0;
+        for i in 0..entries.len() {
+            if bucket_of(&entries[i]) <= best_bucket {
+                entries.swap(i, mid);
+                mid += 1;
+            }
+        }
+
+        if mid == 0 || mid == entries.len() {
+            mid = entries.len() / 2;
+        }
+
+        Some(mid)
+    }
+
+    fn child(entries: &mut [BuildEntry]) -> Arc<dyn Hittable> {
+        if entries.len() == 1 {
+            entries[0].object.clone()
+        } else {
+            Arc::new(BvhNode::build(entries))
+        }
+    }
+
+    fn leaf(entries: &[BuildEntry], bbox: Aabb) -> BvhNode {
+        // Split the small group in two lists rather than nesting another level
+        let mid = entries.len() / 2;
+        let mut left = HittableList::new();
+        let mut right = HittableList::new();
+        for e in &entries[..mid] {
+            left.add(e.object.clone());
+        }
+        for e in &entries[mid..] {
+            right.add(e.object.clone());
+        }
+
+        BvhNode {
+            left: Arc::new(left),
+            right: Some(Arc::new(right)),
+            bbox: Some(bbox),
+        }
+    }
+}
+
+impl BvhNode {
+    fn misses(&self, r: &Ray, t_min: f64, t_max: f64) -> bool {
+        match &self.bbox {
+            Some(bbox) => !bbox.hit(r, t_min, t_max),
+            None => false,
+        }
+    }
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        if self.misses(r, t_min, t_max) {
+            return false;
+        }
+
+        let hit_left = self.left.hit(r, t_min, t_max, rec);
+        // Only look for hits on the right that are closer than the left one
+        let hit_right = self
+            .right
+            .as_ref()
+            .is_some_and(|right| right.hit(r, t_min, if hit_left { rec.t } else { t_max }, rec));
+
+        hit_left || hit_right
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        match self.bbox {
+            Some(bbox) => {
+                *output_box = bbox;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn shadow_hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        if self.misses(r, t_min, t_max) {
+            return false;
+        }
+
+        let hit_left = self.left.shadow_hit(r, t_min, t_max, rec);
+        let hit_right = self.right.as_ref().is_some_and(|right| {
+            right.shadow_hit(r, t_min, if hit_left { rec.t } else { t_max }, rec)
+        });
+
+        hit_left || hit_right
+    }
+
+    fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64) -> f64 {
+        if self.misses(r, t_min, t_max) {
+            return 1.0;
+        }
+
+        let right = match &self.right {
+            Some(right) => right.transmittance(r, t_min, t_max),
+            None => 1.0,
+        };
+        self.left.transmittance(r, t_min, t_max) * right
+    }
+}
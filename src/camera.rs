@@ -1,4 +1,8 @@
-use crate::{math::deg_to_rad, ray::Ray, vec3::*};
+use crate::{
+    math::{deg_to_rad, rand_unit},
+    ray::Ray,
+    vec3::*,
+};
 
 pub struct Camera {
     origin: Point3,
@@ -7,8 +11,10 @@ pub struct Camera {
     vertical: Vec3,
     x: Vec3,
     y: Vec3,
-    z: Vec3,
     lens_radius: f64,
+    // Rays are fired at random times between these
+    shutter_open: f64,
+    shutter_close: f64,
 }
 
 impl Camera {
@@ -42,22 +48,37 @@ impl Camera {
             lower_left_corner: origin - (horizontal / 2.0) - (vertical / 2.0) - z * focus_dist,
             x,
             y,
-            z,
             lens_radius: aperture / 2.0,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
         }
     }
 
+    pub fn with_shutter(mut self, open: f64, close: f64) -> Camera {
+        self.shutter_open = open;
+        self.shutter_close = close;
+        self
+    }
+
     pub fn get_ray(&self, u: f64, v: f64) -> Ray {
         // depth of field scattering
         let rd = Vec3::rand_in_disk() * self.lens_radius;
         let offset = self.x * rd.x + self.y * rd.y;
 
+        // Static scenes don't spend a random number on the time
+        let time = if self.shutter_close > self.shutter_open {
+            self.shutter_open + (self.shutter_close - self.shutter_open) * rand_unit()
+        } else {
+            self.shutter_open
+        };
+
         // Ray from camera to a point on the viewport surface
         Ray {
             origin: self.origin + offset,
             dir: self.lower_left_corner + self.horizontal * u + self.vertical * v
                 - self.origin
                 - offset,
+            time,
         }
     }
 }
@@ -0,0 +1,318 @@
+use std::path::PathBuf;
+
+use crate::color::ToneMap;
+use crate::output::BitDepth;
+
+pub const USAGE: &str = "\
+Usage: raytracing [OPTIONS] [SCENE]
+
+Renders SCENE, either a TOML scene file or the name of a built in scene
+(random_balls, the default).
+
+Options:
+  -o, --output <PATH>      Image file to write, .png, .ppm, .exr, .hdr
+                           or .pfm [default: image.ppm]
+  -b, --bit-depth <BITS>   Bits per channel, 8 or 16 for .png and .ppm
+                           [default: 8], 16 (half) or 32 for .exr
+                           [default: 32]
+      --ascii-ppm          Write .ppm files as plain text P3
+  -e, --exposure <STOPS>   Exposure adjustment for .png and .ppm
+                           [default: 0]
+      --tone-map <CURVE>   clamp or reinhard, for .png and .ppm
+                           [default: clamp]
+  -W, --width <PIXELS>     Image width, overriding the scene's
+  -H, --height <PIXELS>    Image height, overriding the scene's
+  -a, --aspect <RATIO>     Aspect ratio as a number or W:H, e.g. 16:9
+  -s, --samples <COUNT>    Samples per pixel
+  -d, --depth <COUNT>      Maximum bounces per path
+      --roulette-depth <COUNT>
+                           Bounces before paths may be ended early by
+                           Russian roulette [default: 5]
+  -t, --tile <W>x<H>       Tile size in pixels, or a single number for
+                           square tiles [default: 80x45]
+  -j, --threads <COUNT>    Render threads [default: available cores]
+      --seed <SEED>        Seed for reproducible renders
+      --scene <SCENE>      Same as the SCENE argument
+  -h, --help               Print this message
+";
+
+// Whether a flag takes a value, or None if it isn't one we know
+fn takes_value(flag: &str) -> Option<bool> {
+    match flag {
+        "-h" | "--help" | "--ascii-ppm" => Some(false),
+        "-o" | "--output" | "-b" | "--bit-depth" | "-e" | "--exposure" | "--tone-map" | "-W"
+        | "--width" | "-H" | "--height" | "-a" | "--aspect" | "-s" | "--samples" | "-d"
+        | "--depth" | "-t" | "--tile" | "-j" | "--threads" | "--seed" | "--scene"
+        | "--roulette-depth" => Some(true),
+        _ => None,
+    }
+}
+
+pub struct Options {
+    pub output: PathBuf,
+    pub bit_depth: Option<BitDepth>,
+    pub ascii_ppm: bool,
+    pub exposure: f64,
+    pub tone_map: ToneMap,
+    pub scene: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub aspect_ratio: Option<f64>,
+    pub samples: Option<u32>,
+    pub bounce_depth: Option<u32>,
+    pub roulette_depth: Option<u32>,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub threads: Option<usize>,
+    pub seed: Option<u64>,
+    pub help: bool,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            output: PathBuf::from("image.ppm"),
+            bit_depth: None,
+            ascii_ppm: false,
+            exposure: 0.0,
+            tone_map: ToneMap::Clamp,
+            scene: None,
+            width: None,
+            height: None,
+            aspect_ratio: None,
+            samples: None,
+            bounce_depth: None,
+            roulette_depth: None,
+            tile_width: 80,
+            tile_height: 45,
+            threads: None,
+            seed: None,
+            help: false,
+        }
+    }
+}
+
+fn parse_positive<T: std::str::FromStr + PartialOrd + Default>(
+    flag: &str,
+    value: &str,
+) -> Result<T, String> {
+    match value.parse::<T>() {
+        Ok(n) if n > T::default() => Ok(n),
+        _ => Err(format!(
+            "{} expects a positive number, got '{}'",
+            flag, value
+        )),
+    }
+}
+
+fn parse_aspect(value: &str) -> Result<f64, String> {
+    let ratio = match value.split_once(':') {
+        Some((w, h)) => match (w.parse::<f64>(), h.parse::<f64>()) {
+            (Ok(w), Ok(h)) => w / h,
+            _ => f64::NAN,
+        },
+        None => value.parse().unwrap_or(f64::NAN),
+    };
+
+    if ratio.is_finite() && ratio > 0.0 {
+        Ok(ratio)
+    } else {
+        Err(format!(
+            "--aspect expects a positive ratio like 1.5 or 16:9, got '{}'",
+            value
+        ))
+    }
+}
+
+fn parse_tile(value: &str) -> Result<(u32, u32), String> {
+    match value.split_once('x') {
+        Some((w, h)) => Ok((parse_positive("--tile", w)?, parse_positive("--tile", h)?)),
+        None => {
+            let size = parse_positive("--tile", value)?;
+            Ok((size, size))
+        }
+    }
+}
+
+// Parses the arguments after the program name. Flags take their value either
+// as the next argument, after an '=' for long flags, or attached for short
+// ones, e.g. --samples 50, --samples=50 or -s50
+pub fn parse_args<I: Iterator<Item = String>>(args: I) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args;
+
+    while let Some(arg) = args.next() {
+        if !arg.starts_with('-') {
+            if options.scene.replace(arg).is_some() {
+                return Err("only one scene can be rendered at a time".to_string());
+            }
+            continue;
+        }
+
+        let (flag, attached) = if let Some(long) = arg.strip_prefix("--") {
+            match long.split_once('=') {
+                Some((name, value)) => (format!("--{}", name), Some(value.to_string())),
+                None => (arg.clone(), None),
+            }
+        } else if arg.len() > 2 && arg.is_char_boundary(2) {
+            (arg[..2].to_string(), Some(arg[2..].to_string()))
+        } else {
+            (arg.clone(), None)
+        };
+
+        match takes_value(&flag) {
+            None => return Err(format!("unknown option '{}'", arg)),
+            Some(false) => {
+                match flag.as_str() {
+                    "--ascii-ppm" => options.ascii_ppm = true,
+                    _ => options.help = true,
+                }
+                continue;
+            }
+            Some(true) => {}
+        }
+
+        let value = match attached.or_else(|| args.next()) {
+            Some(value) => value,
+            None => return Err(format!("{} expects a value", flag)),
+        };
+        let v = value.as_str();
+
+        match flag.as_str() {
+            "-o" | "--output" => options.output = PathBuf::from(v),
+            "-b" | "--bit-depth" => {
+                options.bit_depth = Some(match v {
+                    "8" => BitDepth::Eight,
+                    "16" => BitDepth::Sixteen,
+                    "32" => BitDepth::ThirtyTwo,
+                    _ => return Err(format!("{} expects 8, 16 or 32, got '{}'", flag, v)),
+                })
+            }
+            "-e" | "--exposure" => {
+                options.exposure = match v.parse::<f64>() {
+                    Ok(stops) if stops.is_finite() => stops,
+                    _ => return Err(format!("{} expects a number of stops, got '{}'", flag, v)),
+                }
+            }
+            "--tone-map" => {
+                options.tone_map = match v {
+                    "clamp" => ToneMap::Clamp,
+                    "reinhard" => ToneMap::Reinhard,
+                    _ => return Err(format!("--tone-map expects clamp or reinhard, got '{}'", v)),
+                }
+            }
+            "-W" | "--width" => options.width = Some(parse_positive(&flag, v)?),
+            "-H" | "--height" => options.height = Some(parse_positive(&flag, v)?),
+            "-a" | "--aspect" => options.aspect_ratio = Some(parse_aspect(v)?),
+            "-s" | "--samples" => options.samples = Some(parse_positive(&flag, v)?),
+            "-d" | "--depth" => options.bounce_depth = Some(parse_positive(&flag, v)?),
+            "--roulette-depth" => {
+                options.roulette_depth =
+                    Some(v.parse().map_err(|_| {
+                        format!("--roulette-depth expects a whole number, got '{}'", v)
+                    })?)
+            }
+            "-t" | "--tile" => {
+                (options.tile_width, options.tile_height) = parse_tile(v)?;
+            }
+            "-j" | "--threads" => options.threads = Some(parse_positive(&flag, v)?),
+            "--seed" => {
+                options.seed = Some(
+                    v.parse()
+                        .map_err(|_| format!("--seed expects an integer, got '{}'", v))?,
+                )
+            }
+            "--scene" => {
+                if options.scene.replace(value).is_some() {
+                    return Err("only one scene can be rendered at a time".to_string());
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    if options.width.is_some() && options.height.is_some() && options.aspect_ratio.is_some() {
+        return Err("give at most two of --width, --height and --aspect".to_string());
+    }
+
+    Ok(options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    // Arguments, and whether they set the option they name
+    type Case = (&'static [&'static str], fn(&Options) -> bool);
+
+    // Every flag that takes a value, in each of the forms it can be given
+    #[test]
+    fn value_flags() {
+        let cases: &[Case] = &[
+            (&["-o", "out.png"], |o| o.output == Path::new("out.png")),
+            (&["--output=out.png"], |o| o.output == Path::new("out.png")),
+            (&["-b16"], |o| {
+                matches!(o.bit_depth, Some(BitDepth::Sixteen))
+            }),
+            (&["--bit-depth", "32"], |o| {
+                matches!(o.bit_depth, Some(BitDepth::ThirtyTwo))
+            }),
+            (&["-e", "-1.5"], |o| o.exposure == -1.5),
+            (&["--exposure=2"], |o| o.exposure == 2.0),
+            (&["--tone-map", "reinhard"], |o| {
+                matches!(o.tone_map, ToneMap::Reinhard)
+            }),
+            (&["-W", "640"], |o| o.width == Some(640)),
+            (&["--width=640"], |o| o.width == Some(640)),
+            (&["-H480"], |o| o.height == Some(480)),
+            (&["--height", "480"], |o| o.height == Some(480)),
+            (&["-a", "16:9"], |o| o.aspect_ratio == Some(16.0 / 9.0)),
+            (&["--aspect=1.5"], |o| o.aspect_ratio == Some(1.5)),
+            (&["-s", "50"], |o| o.samples == Some(50)),
+            (&["--samples=50"], |o| o.samples == Some(50)),
+            (&["-d", "8"], |o| o.bounce_depth == Some(8)),
+            (&["--depth", "8"], |o| o.bounce_depth == Some(8)),
+            (&["--roulette-depth", "0"], |o| o.roulette_depth == Some(0)),
+            (&["-t", "32"], |o| (o.tile_width, o.tile_height) == (32, 32)),
+            (&["--tile=64x16"], |o| {
+                (o.tile_width, o.tile_height) == (64, 16)
+            }),
+            (&["-j", "3"], |o| o.threads == Some(3)),
+            (&["--threads", "3"], |o| o.threads == Some(3)),
+            (&["--seed", "42"], |o| o.seed == Some(42)),
+            (&["--scene", "cornell.toml"], |o| {
+                o.scene.as_deref() == Some("cornell.toml")
+            }),
+        ];
+
+        for (args, check) in cases {
+            match parse(args) {
+                Ok(options) => assert!(check(&options), "{:?} set the wrong option", args),
+                Err(why) => panic!("{:?} failed to parse: {}", args, why),
+            }
+        }
+    }
+
+    #[test]
+    fn value_flags_need_values() {
+        for flag in ["-o", "--exposure", "--tone-map", "--seed", "--scene"] {
+            assert_eq!(
+                parse(&[flag]).err(),
+                Some(format!("{} expects a value", flag))
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_flags() {
+        assert_eq!(
+            parse(&["--exposer", "1"]).err(),
+            Some("unknown option '--exposer'".to_string())
+        );
+    }
+}
@@ -1,23 +1,33 @@
 use crate::math::clamp;
-use std::io::{BufWriter, Write};
 
 use crate::vec3::Color;
 
-pub fn write_color<T: Write>(out: &mut BufWriter<T>, c: &Color) {
-    out.write(format!("{} {} {}\n", c.x as u32, c.y as u32, c.z as u32).as_bytes())
-        .unwrap();
+#[derive(Clone, Copy, PartialEq)]
+pub enum ToneMap {
+    // Cuts everything above 1 off, matching the look of the original renders
+    Clamp,
+    // Compresses highlights smoothly with c / (1 + c) per channel
+    Reinhard,
 }
 
-pub fn process_color(color: &Color, samples_per_pixel: u32) -> Color {
-    let scale = 1.0 / samples_per_pixel as f64;
-    // sqrt is for gamma correction with gamma = 2
-    let r = (color.x * scale).sqrt();
-    let g = (color.y * scale).sqrt();
-    let b = (color.z * scale).sqrt();
+// Maps linear radiance onto display values in [0, 1]. Exposure is in stops,
+// so each +1 doubles the brightness before the curve is applied
+pub fn tone_map(color: &Color, operator: ToneMap, exposure: f64) -> Color {
+    let exposed = *color * 2f64.powf(exposure);
 
-    let ir = clamp(r, 0.0, 0.999) * 256.0;
-    let ig = clamp(g, 0.0, 0.999) * 256.0;
-    let ib = clamp(b, 0.0, 0.999) * 256.0;
+    let mapped = match operator {
+        ToneMap::Clamp => exposed,
+        ToneMap::Reinhard => Color::new(
+            exposed.x / (1.0 + exposed.x),
+            exposed.y / (1.0 + exposed.y),
+            exposed.z / (1.0 + exposed.z),
+        ),
+    };
 
-    Color::new(ir, ig, ib)
+    // sqrt is for gamma correction with gamma = 2
+    Color::new(
+        clamp(mapped.x, 0.0, 1.0).sqrt(),
+        clamp(mapped.y, 0.0, 1.0).sqrt(),
+        clamp(mapped.z, 0.0, 1.0).sqrt(),
+    )
 }
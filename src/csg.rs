@@ -0,0 +1,142 @@
+use std::sync::Arc;
+
+use crate::aabb::{surrounding_box, Aabb};
+use crate::hittable::*;
+use crate::ray::Ray;
+use crate::vec3::Point3;
+
+#[derive(Clone, Copy)]
+pub enum CsgOp {
+    // Inside either object
+    Union,
+    // Inside both objects
+    Intersection,
+    // Inside a but not b
+    Difference,
+}
+
+impl CsgOp {
+    fn contains(self, in_a: bool, in_b: bool) -> bool {
+        match self {
+            CsgOp::Union => in_a || in_b,
+            CsgOp::Intersection => in_a && in_b,
+            CsgOp::Difference => in_a && !in_b,
+        }
+    }
+}
+
+// Boolean combination of two closed objects, whose hits' front faces tell
+// whether the ray is going in or out of them. The ray is followed through
+// every surface of both, and the combination is hit wherever being inside
+// it changes. Surfaces keep their own materials, with those of b cut out
+// of a facing into the hole
+pub struct Csg {
+    op: CsgOp,
+    a: Arc<dyn Hittable>,
+    b: Arc<dyn Hittable>,
+    // None when unbounded, like a union with a plane
+    bbox: Option<Aabb>,
+}
+
+impl Csg {
+    pub fn new(op: CsgOp, a: Arc<dyn Hittable>, b: Arc<dyn Hittable>) -> Csg {
+        let mut box_a = Aabb::empty();
+        let mut box_b = Aabb::empty();
+        let box_a = a.bounding_box(&mut box_a).then_some(box_a);
+        let box_b = b.bounding_box(&mut box_b).then_some(box_b);
+
+        let bbox = match (op, box_a, box_b) {
+            (CsgOp::Union, Some(box_a), Some(box_b)) => Some(surrounding_box(&box_a, &box_b)),
+            (CsgOp::Union, _, _) => None,
+            // Half spaces like planes can cut a bounded object
+            (CsgOp::Intersection, Some(box_a), Some(box_b)) => Some(Aabb::new(
+                Point3::new(
+                    box_a.min.x.max(box_b.min.x),
+                    box_a.min.y.max(box_b.min.y),
+                    box_a.min.z.max(box_b.min.z),
+                ),
+                Point3::new(
+                    box_a.max.x.min(box_b.max.x),
+                    box_a.max.y.min(box_b.max.y),
+                    box_a.max.z.min(box_b.max.z),
+                ),
+            )),
+            (CsgOp::Intersection, box_a, box_b) => box_a.or(box_b),
+            (CsgOp::Difference, box_a, _) => box_a,
+        };
+
+        Csg { op, a, b, bbox }
+    }
+
+    pub fn union(a: Arc<dyn Hittable>, b: Arc<dyn Hittable>) -> Csg {
+        Csg::new(CsgOp::Union, a, b)
+    }
+
+    pub fn intersection(a: Arc<dyn Hittable>, b: Arc<dyn Hittable>) -> Csg {
+        Csg::new(CsgOp::Intersection, a, b)
+    }
+
+    pub fn difference(a: Arc<dyn Hittable>, b: Arc<dyn Hittable>) -> Csg {
+        Csg::new(CsgOp::Difference, a, b)
+    }
+}
+
+impl Hittable for Csg {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        if let Some(bbox) = self.bbox {
+            if !bbox.hit(r, t_min, t_max) {
+                return false;
+            }
+        }
+
+        let a = crossings(self.a.as_ref(), r, t_min);
+        if a.is_empty() && !matches!(self.op, CsgOp::Union) {
+            return false;
+        }
+        let b = crossings(self.b.as_ref(), r, t_min);
+
+        // Starting inside an object means its first crossing is on the way out
+        let mut in_a = a.first().is_some_and(|c| !c.front_face);
+        let mut in_b = b.first().is_some_and(|c| !c.front_face);
+        let inside = self.op.contains(in_a, in_b);
+
+        // Merge the two lists of crossings by distance
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() || j < b.len() {
+            let from_a = j == b.len() || (i < a.len() && a[i].t <= b[j].t);
+            let crossing = if from_a {
+                in_a = a[i].front_face;
+                i += 1;
+                &a[i - 1]
+            } else {
+                in_b = b[j].front_face;
+                j += 1;
+                &b[j - 1]
+            };
+            if crossing.t > t_max {
+                return false;
+            }
+
+            let now_inside = self.op.contains(in_a, in_b);
+            if now_inside != inside {
+                // The normal already faces the ray, only whether it's going
+                // in or out can differ from the object it came from
+                *rec = crossing.clone();
+                rec.front_face = now_inside;
+                return true;
+            }
+        }
+
+        false
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        match self.bbox {
+            Some(bbox) => {
+                *output_box = bbox;
+                true
+            }
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,302 @@
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hittable::*;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::triangle::intersect;
+use crate::vec3::*;
+
+// Magic bytes at the start of a raw height grid file
+const HEIGHTS_MAGIC: &[u8; 4] = b"HGRD";
+
+// Grid of heights, nx samples across and nz deep. Loaded from a grayscale
+// image, with black at 0 and white at 1, or from a raw file of:
+//
+//     4 bytes   "HGRD"
+//     2 x u32   nx, nz, little endian
+//     f32 x n   n = nx * nz heights, little endian, with x varying fastest
+//
+// Rows of an image run along z, with its top row furthest back at -z
+pub struct HeightMap {
+    pub nx: usize,
+    pub nz: usize,
+    pub heights: Vec<f32>,
+}
+
+impl HeightMap {
+    pub fn new(nx: usize, nz: usize, heights: Vec<f32>) -> Result<HeightMap, String> {
+        if nx < 2 || nz < 2 {
+            return Err("needs at least 2 heights along each side".to_string());
+        }
+        if nx.checked_mul(nz) != Some(heights.len()) {
+            return Err(format!(
+                "expected one height per sample of a {}x{} grid, found {}",
+                nx,
+                nz,
+                heights.len()
+            ));
+        }
+        if heights.iter().any(|h| !h.is_finite()) {
+            return Err("heights must be finite".to_string());
+        }
+        Ok(HeightMap { nx, nz, heights })
+    }
+
+    pub fn load(path: &Path) -> Result<HeightMap, String> {
+        let bytes =
+            fs::read(path).map_err(|why| format!("could not read {}: {}", path.display(), why))?;
+        let error = |message: &str| format!("{}: {}", path.display(), message);
+
+        let (nx, nz, heights) = if bytes.starts_with(HEIGHTS_MAGIC) {
+            if bytes.len() < 12 {
+                return Err(error("height grid header is cut short"));
+            }
+            let dim = |i: usize| {
+                u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]) as usize
+            };
+            let (nx, nz) = (dim(4), dim(8));
+            let count = nx
+                .checked_mul(nz)
+                .ok_or_else(|| error("grid dimensions are too large"))?;
+            let data_len = count
+                .checked_mul(4)
+                .ok_or_else(|| error("grid dimensions are too large"))?;
+            if bytes.len() - 12 != data_len {
+                return Err(error(&format!(
+                    "expected {} heights for a {}x{} grid, found {} bytes of data",
+                    count,
+                    nx,
+                    nz,
+                    bytes.len() - 12
+                )));
+            }
+
+            let heights = bytes[12..]
+                .chunks_exact(4)
+                .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+                .collect();
+            (nx, nz, heights)
+        } else {
+            // Heights are data rather than colors, so the pixel values are
+            // used as they are without undoing any gamma
+            let image = image::load_from_memory(&bytes)
+                .map_err(|why| format!("could not read {}: {}", path.display(), why))?
+                .to_luma32f();
+            let (nx, nz) = (image.width() as usize, image.height() as usize);
+            (nx, nz, image.into_raw())
+        };
+
+        HeightMap::new(nx, nz, heights).map_err(|why| error(&why))
+    }
+
+    fn height(&self, i: usize, j: usize) -> f64 {
+        self.heights[j * self.nx + i] as f64
+    }
+}
+
+// Terrain over a height map, with heights used as y and the grid spread
+// over a rectangle centered on the origin whose longer side is 1 unit, to
+// be sized with a transform. Each cell of four samples is split into two
+// triangles, found by walking the cells under the ray in order rather
+// than putting every triangle in a BVH. Normals are smoothed across
+// triangles and uvs cover the whole map once
+pub struct Heightfield {
+    map: Arc<HeightMap>,
+    mat: Arc<dyn Material>,
+    // Spacing between samples
+    cell: f64,
+    // Position of the first sample, at the -x, -z corner
+    origin: Point3,
+    bbox: Aabb,
+}
+
+impl Heightfield {
+    pub fn new(map: Arc<HeightMap>, mat: Arc<dyn Material>) -> Heightfield {
+        let cell = 1.0 / (map.nx.max(map.nz) - 1) as f64;
+        let half_x = 0.5 * cell * (map.nx - 1) as f64;
+        let half_z = 0.5 * cell * (map.nz - 1) as f64;
+
+        let (low, high) = map
+            .heights
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(low, high), h| {
+                (low.min(*h as f64), high.max(*h as f64))
+            });
+
+        Heightfield {
+            map,
+            mat,
+            cell,
+            origin: Point3::new(-half_x, 0.0, -half_z),
+            bbox: Aabb::new(
+                Point3::new(-half_x, low, -half_z),
+                Point3::new(half_x, high, half_z),
+            )
+            .padded(),
+        }
+    }
+
+    fn vertex(&self, i: usize, j: usize) -> Point3 {
+        Point3::new(
+            self.origin.x + i as f64 * self.cell,
+            self.map.height(i, j),
+            self.origin.z + j as f64 * self.cell,
+        )
+    }
+
+    // Smoothed normal at a sample, from the slope between its neighbours
+    fn vertex_normal(&self, i: usize, j: usize) -> Vec3 {
+        let (i0, i1) = (i.saturating_sub(1), (i + 1).min(self.map.nx - 1));
+        let (j0, j1) = (j.saturating_sub(1), (j + 1).min(self.map.nz - 1));
+        let dx = (self.map.height(i1, j) - self.map.height(i0, j)) / ((i1 - i0) as f64 * self.cell);
+        let dz = (self.map.height(i, j1) - self.map.height(i, j0)) / ((j1 - j0) as f64 * self.cell);
+        normalized(Vec3::new(-dx, 1.0, -dz))
+    }
+
+    // Nearest hit on the two triangles of the cell with corner (i, j)
+    fn hit_cell(
+        &self,
+        r: &Ray,
+        i: usize,
+        j: usize,
+        t_min: f64,
+        t_max: f64,
+        rec: &mut HitRecord,
+    ) -> bool {
+        // Corners ordered so both triangles face up
+        let corners = [(i, j), (i, j + 1), (i + 1, j), (i + 1, j + 1)];
+        let triangles = [
+            [corners[0], corners[1], corners[2]],
+            [corners[3], corners[2], corners[1]],
+        ];
+
+        let mut nearest = None;
+        let mut t_max = t_max;
+        for triangle in triangles {
+            let [v0, v1, v2] = triangle.map(|(i, j)| self.vertex(i, j));
+            if let Some((t, b1, b2)) = intersect(r, &v0, &v1, &v2, t_min, t_max) {
+                t_max = t;
+                nearest = Some((t, triangle, [v0, v1, v2], b1, b2));
+            }
+        }
+
+        let Some((t, triangle, [v0, v1, v2], b1, b2)) = nearest else {
+            return false;
+        };
+
+        // Front face is decided by the true geometric normal
+        let p = r.at(t);
+        let geometric_norm = normalized(cross(&(v1 - v0), &(v2 - v0)));
+        *rec = HitRecord::new(p, t, r, &geometric_norm, self.mat.clone());
+
+        // Interpolated normal for smooth shading, flipped onto the same side
+        // as the geometric normal that HitRecord picked
+        let [n0, n1, n2] = triangle.map(|(i, j)| self.vertex_normal(i, j));
+        let shading_norm = normalized(n0 * (1.0 - b1 - b2) + n1 * b1 + n2 * b2);
+        rec.normal = if dot(&shading_norm, &rec.normal) < 0.0 {
+            -shading_norm
+        } else {
+            shading_norm
+        };
+
+        let extent = self.bbox.max - self.bbox.min;
+        rec.u = (p.x - self.bbox.min.x) / extent.x;
+        rec.v = 1.0 - (p.z - self.bbox.min.z) / extent.z;
+
+        true
+    }
+}
+
+impl Hittable for Heightfield {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let Some((t_enter, t_exit)) = self.bbox.interval(r, t_min, t_max) else {
+            return false;
+        };
+
+        // 2D DDA over the cells the ray passes above or through, in order,
+        // so the first cell with a hit has the nearest one
+        let (cells_x, cells_z) = (self.map.nx - 1, self.map.nz - 1);
+        let start = r.at(t_enter);
+        let cell_of = |x: f64, origin: f64, cells: usize| {
+            (((x - origin) / self.cell).floor().max(0.0) as usize).min(cells - 1)
+        };
+        let mut i = cell_of(start.x, self.origin.x, cells_x);
+        let mut j = cell_of(start.z, self.origin.z, cells_z);
+
+        // Ray parameters where it crosses the next cell boundary along each
+        // axis, and how far apart those crossings are
+        let axis = |dir: f64, origin: f64, index: usize, corner: f64| {
+            if dir.abs() < 1e-12 {
+                return (f64::INFINITY, f64::INFINITY);
+            }
+            let next = if dir > 0.0 { index + 1 } else { index };
+            let boundary = corner + next as f64 * self.cell;
+            ((boundary - origin) / dir, self.cell / dir.abs())
+        };
+        let (mut next_x, delta_x) = axis(r.dir.x, r.origin.x, i, self.origin.x);
+        let (mut next_z, delta_z) = axis(r.dir.z, r.origin.z, j, self.origin.z);
+
+        let mut t_cell = t_enter;
+        loop {
+            let t_leave = next_x.min(next_z).min(t_exit);
+
+            // Only test the triangles if the ray's height over the cell
+            // overlaps the heights of its corners
+            let (y0, y1) = (r.at(t_cell).y, r.at(t_leave).y);
+            let corners = [(i, j), (i + 1, j), (i, j + 1), (i + 1, j + 1)];
+            let (low, high) = corners.iter().fold(
+                (f64::INFINITY, f64::NEG_INFINITY),
+                |(low, high), &(i, j)| {
+                    let h = self.map.height(i, j);
+                    (low.min(h), high.max(h))
+                },
+            );
+            if y0.min(y1) <= high && y0.max(y1) >= low && self.hit_cell(r, i, j, t_min, t_max, rec)
+            {
+                return true;
+            }
+
+            if t_leave >= t_exit {
+                return false;
+            }
+
+            // Step into the neighbouring cell across the nearer boundary
+            t_cell = t_leave;
+            if next_x < next_z {
+                if r.dir.x > 0.0 {
+                    i += 1;
+                    if i >= cells_x {
+                        return false;
+                    }
+                } else {
+                    if i == 0 {
+                        return false;
+                    }
+                    i -= 1;
+                }
+                next_x += delta_x;
+            } else {
+                if r.dir.z > 0.0 {
+                    j += 1;
+                    if j >= cells_z {
+                        return false;
+                    }
+                } else {
+                    if j == 0 {
+                        return false;
+                    }
+                    j -= 1;
+                }
+                next_z += delta_z;
+            }
+        }
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        *output_box = self.bbox;
+        true
+    }
+}
@@ -1,4 +1,6 @@
+use crate::aabb::{surrounding_box, Aabb};
 use crate::material::{Lambertian, Material};
+use crate::math::rand_unit;
 use crate::ray::Ray;
 use crate::vec3::*;
 use std::sync::Arc;
@@ -8,6 +10,12 @@ pub struct HitRecord {
     pub p: Point3,
     pub normal: Vec3,
     pub t: f64,
+    // Surface parameterization of the hit point
+    pub u: f64,
+    pub v: f64,
+    // Weights of a triangle's second and third vertices, the first
+    // vertex's weight is 1 - b1 - b2
+    pub barycentric: (f64, f64),
     pub front_face: bool,
     pub mat: Arc<dyn Material>,
 }
@@ -36,6 +44,9 @@ impl HitRecord {
         HitRecord {
             p,
             t,
+            u: 0.0,
+            v: 0.0,
+            barycentric: (0.0, 0.0),
             front_face,
             normal,
             mat,
@@ -46,6 +57,9 @@ impl HitRecord {
         HitRecord {
             p: Point3::new(0.0, 0.0, 0.0),
             t: 0.0,
+            u: 0.0,
+            v: 0.0,
+            barycentric: (0.0, 0.0),
             front_face: false,
             normal: Vec3::new(0.0, 0.0, 0.0),
             mat: Arc::new(Lambertian::new(0.0, 0.0, 0.0)),
@@ -55,8 +69,56 @@ impl HitRecord {
 
 pub trait Hittable: Send + Sync {
     fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool;
+
+    // Returns false for objects that can't be bounded
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool;
+
+    // Density, over solid angle as seen from origin, of random(origin)
+    // producing direction v. Only objects that can be sampled as lights
+    // need to implement this and random
+    fn pdf_value(&self, _origin: &Point3, _v: &Vec3) -> f64 {
+        0.0
+    }
+
+    // Random direction from origin towards a point on the object
+    fn random(&self, _origin: &Point3) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
+
+    // Like hit, but passing straight through participating media. Shadow
+    // rays use this and account for the media with transmittance instead
+    fn shadow_hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        self.hit(r, t_min, t_max, rec)
+    }
+
+    // Fraction of light making it through any media along the ray between
+    // t_min and t_max. Surfaces don't count, they're found by shadow_hit
+    fn transmittance(&self, _r: &Ray, _t_min: f64, _t_max: f64) -> f64 {
+        1.0
+    }
 }
 
+// Most surface crossings looked for along one ray through one object
+const MAX_CROSSINGS: usize = 64;
+
+// Every surface of object the ray crosses from t_min on, in order. Looks
+// past t_max too, since whether the ray is inside an object comes from
+// the next time it crosses its surface
+pub fn crossings(object: &dyn Hittable, r: &Ray, t_min: f64) -> Vec<HitRecord> {
+    let mut crossings = Vec::new();
+    let mut rec = HitRecord::blank();
+    let mut t = t_min;
+
+    while crossings.len() < MAX_CROSSINGS && object.hit(r, t, f64::INFINITY, &mut rec) {
+        // Step just past the surface so it isn't found again
+        t = rec.t + 1e-7 * rec.t.abs().max(1.0);
+        crossings.push(rec.clone());
+    }
+
+    crossings
+}
+
+#[derive(Default)]
 pub struct HittableList {
     pub objects: Vec<Arc<dyn Hittable>>,
 }
@@ -66,16 +128,30 @@ impl HittableList {
         self.objects.clear();
     }
 
+    pub fn new() -> HittableList {
+        HittableList {
+            objects: Vec::new(),
+        }
+    }
+
     pub fn add(&mut self, new_obj: Arc<dyn Hittable>) {
         self.objects.push(new_obj);
     }
+}
 
-    pub fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+impl HittableList {
+    // Closest hit over every object, found with the given hit function
+    fn nearest(
+        &self,
+        t_max: f64,
+        rec: &mut HitRecord,
+        hit: impl Fn(&dyn Hittable, f64, &mut HitRecord) -> bool,
+    ) -> bool {
         let mut temp_rec = rec.clone();
         let mut hit_anything = false;
 
         self.objects.iter().fold(t_max, |nearest, hittable| {
-            if hittable.hit(r, t_min, nearest, &mut temp_rec) {
+            if hit(hittable.as_ref(), nearest, &mut temp_rec) {
                 hit_anything = true;
                 *rec = temp_rec.clone();
                 temp_rec.t
@@ -87,3 +163,59 @@ impl HittableList {
         hit_anything
     }
 }
+
+impl Hittable for HittableList {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        self.nearest(t_max, rec, |object, nearest, temp_rec| {
+            object.hit(r, t_min, nearest, temp_rec)
+        })
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        if self.objects.is_empty() {
+            return false;
+        }
+
+        let mut temp_box = Aabb::empty();
+        let mut list_box = Aabb::empty();
+
+        for object in &self.objects {
+            if !object.bounding_box(&mut temp_box) {
+                return false;
+            }
+            list_box = surrounding_box(&list_box, &temp_box);
+        }
+
+        *output_box = list_box;
+        true
+    }
+
+    // Sampling picks one object uniformly, so the density is the average
+    // of every object's density
+    fn pdf_value(&self, origin: &Point3, v: &Vec3) -> f64 {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+
+        let sum: f64 = self.objects.iter().map(|o| o.pdf_value(origin, v)).sum();
+        sum / self.objects.len() as f64
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        let index = (rand_unit() * self.objects.len() as f64) as usize;
+        self.objects[index.min(self.objects.len() - 1)].random(origin)
+    }
+
+    fn shadow_hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        self.nearest(t_max, rec, |object, nearest, temp_rec| {
+            object.shadow_hit(r, t_min, nearest, temp_rec)
+        })
+    }
+
+    fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64) -> f64 {
+        self.objects
+            .iter()
+            .map(|o| o.transmittance(r, t_min, t_max))
+            .product()
+    }
+}
@@ -0,0 +1,33 @@
+pub mod aabb;
+pub mod background;
+pub mod bezier;
+pub mod bvh;
+pub mod camera;
+pub mod cli;
+pub mod color;
+pub mod csg;
+pub mod heightfield;
+pub mod hittable;
+pub mod mat4;
+pub mod material;
+pub mod math;
+pub mod medium;
+pub mod mesh;
+pub mod noise;
+pub mod obj;
+pub mod onb;
+pub mod output;
+pub mod plane;
+pub mod poly;
+pub mod quad;
+pub mod quadric;
+pub mod ray;
+pub mod scene;
+pub mod sdf;
+pub mod sphere;
+pub mod texture;
+pub mod torus;
+pub mod transform;
+pub mod triangle;
+pub mod vec3;
+pub mod volume;
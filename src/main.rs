@@ -1,43 +1,28 @@
-mod camera;
-mod color;
-mod hittable;
-mod material;
-mod math;
-mod ray;
-mod sphere;
-mod vec3;
-
-use core::panic;
 use std::env;
-use std::fs::File;
-use std::io::{self, BufWriter, Write};
+use std::io::{self, Write};
 use std::path::Path;
+use std::process;
 use std::sync::mpsc::{self, Sender};
 use std::sync::Arc;
 use std::thread;
 
-use hittable::HitRecord;
-
-use crate::camera::Camera;
-use crate::hittable::HittableList;
-use crate::material::*;
-use crate::math::rand_unit;
-use crate::ray::Ray;
-use crate::sphere::Sphere;
-use crate::vec3::*;
-
-#[derive(Clone, Copy)]
-struct Image {
-    height: u32,
-    width: u32,
-    samples: u32,
-    bounce_depth: u32,
-}
+use raytracing::background::Background;
+use raytracing::bvh::BvhNode;
+use raytracing::camera::Camera;
+use raytracing::cli::{parse_args, Options, USAGE};
+use raytracing::hittable::{HitRecord, Hittable, HittableList};
+use raytracing::material::ScatterRecord;
+use raytracing::math::{rand_unit, seed_thread_rng};
+use raytracing::output::{write_image, EncodeSettings, Framebuffer, ImageFormat};
+use raytracing::ray::Ray;
+use raytracing::scene::{builtin_scene, load_scene, Image, BUILTIN_SCENES};
+use raytracing::vec3::*;
 
 struct Tile {
     buffer: Vec<Vec<Color>>,
     start_x: usize,
     start_y: usize,
+    index: u32,
 }
 
 impl Tile {
@@ -45,6 +30,7 @@ impl Tile {
         let mut tile = Tile {
             start_y: 0,
             start_x: 0,
+            index,
             buffer: Vec::new(),
         };
         tile.resize(height, width, index, image);
@@ -59,6 +45,7 @@ impl Tile {
 
         self.start_x = start_x;
         self.start_y = start_y;
+        self.index = index;
 
         if start_y as u32 >= image.height || start_x as u32 >= image.width {
             return;
@@ -79,153 +66,256 @@ impl Tile {
             self.buffer = vec![vec![Color::new(0.0, 0.0, 0.0); w]; h];
         }
     }
+
+    pub fn copy_into(&self, image: &mut Framebuffer) {
+        // Tile rows count up from the bottom of the image, the framebuffer's
+        // count down from the top
+        for (y, row) in self.buffer.iter().enumerate() {
+            let fb_y = image.height - 1 - (self.start_y + y);
+            for (x, c) in row.iter().enumerate() {
+                image.set(self.start_x + x, fb_y, *c);
+            }
+        }
+    }
+}
+
+// Everything a render thread needs to trace rays through the scene
+struct RenderScene {
+    world: BvhNode,
+    // Emissive objects sampled directly at each diffuse bounce
+    lights: HittableList,
+    // Sampled directly too, when it supports it
+    background: Arc<dyn Background>,
 }
 
-fn ray_color(r: &Ray, world: &HittableList, depth: u32) -> Color {
-    let mut rec = HitRecord::blank();
+// Power heuristic with beta = 2, weighting a sample by how likely its own
+// strategy was to produce it compared to the other one
+fn mis_weight(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0.0 {
+        a / (a + b)
+    } else {
+        0.0
+    }
+}
 
-    if depth == 0 {
-        return Color::new(0.0, 0.0, 0.0);
+// Light reaching p along a shadow ray towards a randomly chosen light,
+// weighted against the material having sampled the same direction
+fn sample_lights(r_in: &Ray, rec: &HitRecord, scene: &RenderScene) -> Color {
+    let black = Color::new(0.0, 0.0, 0.0);
+    if scene.lights.objects.is_empty() {
+        return black;
     }
 
-    // 0.001 min Removes shadow acne. Don't want bounced rays colliding
-    // with the same surface at t = 1e-8 from fp inaccuracies
-    if world.hit(r, 0.001, f64::MAX, &mut rec) {
-        let mut scattered = Ray::blank();
-        let mut attenuation = Color::new(0.0, 0.0, 0.0);
+    let to_light = Ray::new(rec.p, scene.lights.random(&rec.p), r_in.time);
+    let light_pdf = scene.lights.pdf_value(&rec.p, &to_light.dir);
+    let bsdf_pdf = rec.mat.pdf(r_in, rec, &to_light.dir);
+    if light_pdf <= 0.0 || bsdf_pdf <= 0.0 {
+        return black;
+    }
 
-        if rec.mat.scatter(r, &rec, &mut attenuation, &mut scattered) {
-            attenuation * ray_color(&scattered, world, depth - 1)
-        } else {
-            Color::new(0.0, 0.0, 0.0)
-        }
-    } else {
-        let unit_dir = vec3::normalized(r.dir);
-        let t = 0.5 * (unit_dir.y + 1.0);
+    // Whatever the shadow ray hits first is what's visible, which might be
+    // a different light than the one sampled
+    let mut light_rec = HitRecord::blank();
+    if !scene
+        .world
+        .shadow_hit(&to_light, 0.001, f64::MAX, &mut light_rec)
+    {
+        return black;
+    }
 
-        // Linear blend from white to blue
-        Color::new(1.0, 1.0, 1.0) * (1.0 - t) + Color::new(0.5, 0.7, 1.0) * t
+    // Dimmed by any fog in the way
+    let transmittance = scene.world.transmittance(&to_light, 0.001, light_rec.t);
+    if transmittance <= 0.0 {
+        return black;
     }
+
+    light_rec.mat.emitted(&light_rec) * rec.mat.eval(r_in, rec, &to_light.dir) / light_pdf
+        * (transmittance * mis_weight(light_pdf, bsdf_pdf))
 }
 
-fn main() {
-    // Threading
-    let args: Vec<String> = env::args().collect();
-    let mut threads;
-    if args.len() > 1 {
-        threads = args[1].strip_prefix("-j").unwrap().parse().unwrap();
-    } else {
-        threads = thread::available_parallelism().unwrap().get();
+// Light reaching p from the background along a shadow ray that escapes
+// the scene, weighted against the material having sampled it
+fn sample_background(r_in: &Ray, rec: &HitRecord, scene: &RenderScene) -> Color {
+    let black = Color::new(0.0, 0.0, 0.0);
+
+    let to_sky = Ray::new(rec.p, scene.background.random(), r_in.time);
+    let background_pdf = scene.background.pdf_value(&to_sky.dir);
+    let bsdf_pdf = rec.mat.pdf(r_in, rec, &to_sky.dir);
+    if background_pdf <= 0.0 || bsdf_pdf <= 0.0 {
+        return black;
     }
-    eprintln!("Running on {} threads", threads);
 
-    // Output file
-    let path = Path::new("image.ppm");
-    let display = path.display();
-
-    // Image consts
-    let aspect_ratio = 16.0 / 9.0;
-    let image_width = 400;
-    let image = Image {
-        width: image_width,
-        height: (image_width as f64 / aspect_ratio) as u32,
-        samples: 100,
-        bounce_depth: 50,
-    };
+    let mut blocker = HitRecord::blank();
+    if scene
+        .world
+        .shadow_hit(&to_sky, 0.001, f64::MAX, &mut blocker)
+    {
+        return black;
+    }
 
-    // Materials
-    let material_ground = Arc::new(Lambertian::new(0.8, 0.8, 0.6));
-    let material_diffuse = Arc::new(Lambertian::new(0.7, 0.3, 0.3));
-    // let material_center = Arc::new(Dielectric::new(1.5));
-    let material_metal = Arc::new(Metal::new(0.8, 0.8, 0.8, 0.3));
-    // let material_right = Arc::new(Metal::new(0.8, 0.6, 0.2, 0.9));
-    let material_glass = Arc::new(Dielectric::new(1.5));
-
-    // World
-    let mut world = HittableList {
-        objects: Vec::new(),
-    };
-    world.add(Arc::new(Sphere::new(
-        Point3::new(0.0, -1000.0, 0.0),
-        1000.0,
-        material_ground.clone(),
-    )));
-    world.add(Arc::new(Sphere::new(
-        Point3::new(-4.0, 1.0, 0.0),
-        1.0,
-        material_diffuse.clone(),
-    )));
-    world.add(Arc::new(Sphere::new(
-        Point3::new(0.0, 1.0, 0.0),
-        1.0,
-        material_glass.clone(),
-    )));
-    world.add(Arc::new(Sphere::new(
-        Point3::new(4.0, 1.0, 0.0),
-        1.0,
-        material_metal.clone(),
-    )));
-
-    // Generate balls
-    let radius = 0.2;
-    for x in -11..11 {
-        for z in -11..11 {
-            let center = Point3::new(
-                x as f64 + 0.9 * rand_unit(),
-                0.2,
-                z as f64 + 0.9 * rand_unit(),
-            );
-
-            let material: Arc<dyn Material>;
-            let mat_type = rand_unit();
-
-            if mat_type < 0.7 {
-                material = Arc::new(Lambertian {
-                    albedo: Color::rand() * Color::rand(),
-                });
-            } else if mat_type < 0.9 {
-                material = Arc::new(Metal {
-                    albedo: Color::rand_range(0.5, 1.0),
-                    fuzz: rand_unit(),
-                });
-            } else {
-                material = Arc::new(Dielectric::new(rand_unit() + 1.0));
+    let transmittance = scene.world.transmittance(&to_sky, 0.001, f64::MAX);
+    if transmittance <= 0.0 {
+        return black;
+    }
+
+    scene.background.color(&to_sky) * rec.mat.eval(r_in, rec, &to_sky.dir) / background_pdf
+        * (transmittance * mis_weight(background_pdf, bsdf_pdf))
+}
+
+// Follows one path from the camera, adding up the light found at each
+// bounce scaled by the throughput, the fraction of it that makes it back
+// along the path
+fn ray_color(r: Ray, scene: &RenderScene, image: &Image) -> Color {
+    let mut color = Color::new(0.0, 0.0, 0.0);
+    let mut throughput = Color::new(1.0, 1.0, 1.0);
+    let mut ray = r;
+    // Density the last bounce sampled ray with, 0 for camera rays and
+    // specular bounces that lights can't be sampled for
+    let mut bsdf_pdf = 0.0;
+
+    for bounce in 0..image.bounce_depth {
+        let mut rec = HitRecord::blank();
+
+        // 0.001 min Removes shadow acne. Don't want bounced rays colliding
+        // with the same surface at t = 1e-8 from fp inaccuracies
+        if !scene.world.hit(&ray, 0.001, f64::MAX, &mut rec) {
+            let mut sky = scene.background.color(&ray);
+            if bsdf_pdf > 0.0 {
+                sky *= mis_weight(bsdf_pdf, scene.background.pdf_value(&ray.dir));
             }
+            color += throughput * sky;
+            break;
+        }
 
-            world.add(Arc::new(Sphere::new(center, radius, material.clone())));
+        // Lights found by chance share the estimate with direct sampling
+        // from the previous bounce
+        let mut emitted = rec.mat.emitted(&rec);
+        if bsdf_pdf > 0.0 {
+            let light_pdf = scene.lights.pdf_value(&ray.origin, &ray.dir);
+            emitted *= mis_weight(bsdf_pdf, light_pdf);
+        }
+        color += throughput * emitted;
+
+        let mut srec = ScatterRecord::blank();
+        if !rec.mat.sample(&ray, &rec, &mut srec) {
+            break;
+        }
+
+        if srec.is_specular {
+            bsdf_pdf = 0.0;
+        } else {
+            color += throughput * sample_lights(&ray, &rec, scene);
+            color += throughput * sample_background(&ray, &rec, scene);
+            bsdf_pdf = srec.pdf;
+        }
+        throughput *= srec.attenuation;
+        ray = srec.ray;
+
+        // Russian roulette. Paths carrying little light are likely to be
+        // ended, and survivors are scaled up to make up for the ones lost
+        if bounce + 1 >= image.roulette_depth {
+            let survival = throughput.x.max(throughput.y).max(throughput.z).min(0.95);
+            if rand_unit() >= survival {
+                break;
+            }
+            throughput /= survival;
         }
     }
 
-    // Camera
-    let camera_pos = Point3::new(7.5, 1.0, 5.0);
-    let look_at = Point3::new(0.0, 0.0, -1.0);
-    let world_up = Point3::new(0.0, 1.0, 0.0);
-    let dist_to_focus = (camera_pos - look_at).length();
-    let aperture = 0.1;
-
-    let cam = Arc::new(Camera::new(
-        camera_pos,
-        look_at,
-        world_up,
-        30.0,
-        aspect_ratio,
-        aperture,
-        dist_to_focus,
-    ));
-    let mut file = match File::create(path) {
-        Ok(f) => BufWriter::new(f),
-        Err(why) => panic!("Could not create {}: {}", display, why),
+    color
+}
+
+// Command line settings take priority over the scene's. When only one of
+// the dimensions is given the scene's aspect ratio is kept
+fn apply_overrides(scene_image: Image, options: &Options) -> Image {
+    let scene_aspect = scene_image.width as f64 / scene_image.height as f64;
+    let aspect = options.aspect_ratio.unwrap_or(scene_aspect);
+
+    let (width, height) = match (options.width, options.height) {
+        (Some(w), Some(h)) => (w, h),
+        (Some(w), None) => (w, (w as f64 / aspect) as u32),
+        (None, Some(h)) => ((h as f64 * aspect) as u32, h),
+        (None, None) => match options.aspect_ratio {
+            Some(aspect) => (
+                scene_image.width,
+                (scene_image.width as f64 / aspect) as u32,
+            ),
+            None => (scene_image.width, scene_image.height),
+        },
     };
 
-    let header = format!("P3\n{} {}\n255\n", image.width, image.height);
-    file.write(header.as_bytes()).unwrap();
+    Image {
+        width: width.max(1),
+        height: height.max(1),
+        samples: options.samples.unwrap_or(scene_image.samples),
+        bounce_depth: options.bounce_depth.unwrap_or(scene_image.bounce_depth),
+        roulette_depth: options.roulette_depth.unwrap_or(scene_image.roulette_depth),
+    }
+}
+
+fn main() {
+    let options = parse_args(env::args().skip(1)).unwrap_or_else(|why| {
+        eprintln!("Error: {}\nTry --help for usage", why);
+        process::exit(2);
+    });
+    if options.help {
+        print!("{}", USAGE);
+        return;
+    }
+
+    // Threading
+    let mut threads = options
+        .threads
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
+    eprintln!("Running on {} threads", threads);
+
+    // Seed the main thread too, since scenes can be randomly generated
+    if let Some(seed) = options.seed {
+        seed_thread_rng(seed);
+    }
 
-    let iworld = Arc::new(world);
+    // Output file, checked up front so a bad name doesn't waste a render
+    let path = options.output.as_path();
+    let encode = ImageFormat::from_path(path, options.ascii_ppm).and_then(|format| {
+        let settings = EncodeSettings {
+            bit_depth: format.bit_depth(options.bit_depth)?,
+            tone_map: options.tone_map,
+            exposure: options.exposure,
+        };
+        Ok((format, settings))
+    });
+    let (format, settings) = encode.unwrap_or_else(|why| {
+        eprintln!("Error: {}", why);
+        process::exit(2);
+    });
+
+    // Scene
+    let scene_name = options.scene.as_deref().unwrap_or("random_balls");
+    let scene = match builtin_scene(scene_name) {
+        Some(scene) => scene,
+        None => load_scene(Path::new(scene_name)).unwrap_or_else(|why| {
+            eprintln!("Could not load scene: {}", why);
+            if !scene_name.ends_with(".toml") {
+                eprintln!("Built in scenes are: {}", BUILTIN_SCENES.join(", "));
+            }
+            process::exit(1);
+        }),
+    };
+    let image = apply_overrides(scene.image, &options);
+    let cam = Arc::new(scene.camera.build(image.width as f64 / image.height as f64));
+
+    // Wrap the world in a BVH so each ray only tests nearby objects
+    let render_scene = Arc::new(RenderScene {
+        world: BvhNode::new(&scene.world),
+        lights: scene.lights,
+        background: scene.background,
+    });
     let (tx, rx) = mpsc::channel::<Tile>();
 
     let mut requested = 0;
-    let tile_width = 80;
-    let tile_height = 45;
+    let tile_width = options.tile_width;
+    let tile_height = options.tile_height;
     let total_tiles = (image.width as f32 / tile_width as f32).ceil() as u32
         * (image.height as f32 / tile_height as f32).ceil() as u32;
     threads = threads.min(total_tiles as usize);
@@ -234,18 +324,19 @@ fn main() {
     for _ in (0..threads).rev() {
         // Need to clone Arcs because of lifetimes
         let cam_temp = cam.clone();
-        let world_temp = iworld.clone();
+        let scene_temp = render_scene.clone();
         let tx_temp = tx.clone();
 
         let tile = Tile::new(tile_height, tile_width, requested, image);
-        thread::spawn(move || render_tile(tile, cam_temp, world_temp, image, tx_temp));
+        thread::spawn(move || {
+            render_tile(tile, cam_temp, scene_temp, image, options.seed, tx_temp)
+        });
 
         requested += 1;
     }
 
     // Receive scan lines and write into overall buffer
-    let mut image_buffer: Vec<Vec<Color>> =
-        vec![vec![Color::new(0.0, 0.0, 0.0); image.width as usize]; image.height as usize];
+    let mut image_buffer = Framebuffer::new(image.width as usize, image.height as usize);
 
     // As each line is received, launch a new thread for the next line
     // Spawn a thread for each scan line instead of blocking the whole
@@ -255,44 +346,35 @@ fn main() {
     let mut tile: Tile;
     while requested < total_tiles {
         let cam_temp = cam.clone();
-        let world_temp = iworld.clone();
+        let scene_temp = render_scene.clone();
         let tx_temp = tx.clone();
 
         tile = rx.recv().unwrap();
 
-        for (y, row) in tile.buffer.iter().enumerate() {
-            for (x, c) in row.iter().enumerate() {
-                image_buffer[tile.start_y + y][tile.start_x + x] = *c;
-            }
-        }
+        tile.copy_into(&mut image_buffer);
 
         // Resize the same tile and send it back to the new thread to avoid
         // unnecessary memory allocations for the tile buffers
         tile.resize(tile_height, tile_width, requested, image);
 
-        thread::spawn(move || render_tile(tile, cam_temp, world_temp, image, tx_temp));
+        thread::spawn(move || {
+            render_tile(tile, cam_temp, scene_temp, image, options.seed, tx_temp)
+        });
 
         requested += 1;
     }
 
     // Receive the final thread group since there will still be 1 live batch
     for _ in 0..threads {
-        let received = rx.recv().unwrap();
-        for (y, row) in received.buffer.iter().enumerate() {
-            for (x, c) in row.iter().enumerate() {
-                image_buffer[received.start_y + y][received.start_x + x] = *c;
-            }
-        }
+        rx.recv().unwrap().copy_into(&mut image_buffer);
     }
 
     drop(tx);
     assert!(rx.try_iter().next().is_none());
 
-    // Write into ppm file
-    for row in image_buffer.iter().rev() {
-        for pixel in row {
-            crate::color::write_color(&mut file, &pixel);
-        }
+    if let Err(why) = write_image(path, &image_buffer, format, &settings) {
+        eprintln!("Could not write {}: {}", path.display(), why);
+        process::exit(1);
     }
 
     eprint!("\nDone.\n");
@@ -301,15 +383,24 @@ fn main() {
 fn render_tile(
     mut tile: Tile,
     cam: Arc<Camera>,
-    world: Arc<HittableList>,
+    scene: Arc<RenderScene>,
     image: Image,
+    seed: Option<u64>,
     tx: Sender<Tile>,
 ) {
+    // Seeding per tile rather than per thread keeps renders reproducible
+    // regardless of how tiles get scheduled
+    if let Some(seed) = seed {
+        seed_thread_rng(
+            seed.wrapping_add((tile.index as u64 + 1).wrapping_mul(0x9e3779b97f4a7c15)),
+        );
+    }
+
     let h = tile.buffer.len();
     let w = tile.buffer.first().unwrap().len();
 
-    eprint!(
-        "Rendering tile : [{}, {}] - [{}, {}] \n",
+    eprintln!(
+        "Rendering tile : [{}, {}] - [{}, {}] ",
         tile.start_x,
         tile.start_y,
         tile.start_x + w,
@@ -330,10 +421,11 @@ fn render_tile(
                 let v = (line as f64 + rand_unit()) / (image.height as f64 - 1.0);
 
                 let ray = cam.get_ray(u, v);
-                pixel_color += ray_color(&ray, &world, image.bounce_depth);
+                pixel_color += ray_color(ray, &scene, &image);
             }
 
-            tile.buffer[i][j] = crate::color::process_color(&pixel_color, image.samples);
+            // Keep linear radiance, tone mapping is left to the output stage
+            tile.buffer[i][j] = pixel_color / image.samples as f64;
         }
     }
 
@@ -1,77 +1,148 @@
-use crate::{hittable::HitRecord, math::rand_unit, ray::Ray, vec3::*};
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use crate::{
+    hittable::HitRecord,
+    math::rand_unit,
+    onb::Onb,
+    ray::Ray,
+    texture::{SolidColor, Texture},
+    vec3::*,
+};
+
+// Result of sampling a material. For specular materials the direction is
+// the only one carrying light, so pdf and eval don't apply and attenuation
+// is the whole throughput
+pub struct ScatterRecord {
+    pub ray: Ray,
+    // eval / pdf for the sampled direction
+    pub attenuation: Color,
+    pub pdf: f64,
+    pub is_specular: bool,
+}
+
+impl ScatterRecord {
+    pub fn blank() -> ScatterRecord {
+        ScatterRecord {
+            ray: Ray::blank(),
+            attenuation: Color::new(0.0, 0.0, 0.0),
+            pdf: 0.0,
+            is_specular: false,
+        }
+    }
+}
 
 pub trait Material: Send + Sync {
-    fn scatter(
-        &self,
-        r_in: &Ray,
-        rec: &HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
-    ) -> bool;
+    // Picks an outgoing direction. Returns false if the ray is absorbed
+    fn sample(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool;
+
+    // BSDF times the cosine of the outgoing direction with the normal.
+    // Always black for specular materials
+    fn eval(&self, _r_in: &Ray, _rec: &HitRecord, _direction: &Vec3) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    // Density, over solid angle, of sample picking direction. Always zero
+    // for specular materials
+    fn pdf(&self, _r_in: &Ray, _rec: &HitRecord, _direction: &Vec3) -> f64 {
+        0.0
+    }
+
+    // Light given off at the hit point, black for anything that isn't a light
+    fn emitted(&self, _rec: &HitRecord) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    // Lets the scene collect light sources for direct sampling
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    // Lets the scene check that media scatter with a phase function
+    fn is_phase_function(&self) -> bool {
+        false
+    }
 }
 
 pub struct Lambertian {
-    pub albedo: Color,
+    pub albedo: Arc<dyn Texture>,
 }
 
 impl Material for Lambertian {
-    fn scatter(
-        &self,
-        _r_in: &Ray,
-        rec: &HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
-    ) -> bool {
-        // Lambertian scattering for diffuse materials
-        let mut scatter_direction = rec.normal + Vec3::rand_unit();
-
-        if scatter_direction.near_zero() {
-            scatter_direction = rec.normal;
-        }
+    fn sample(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        // Cosine weighted hemisphere sampling cancels the cosine in eval,
+        // leaving just the albedo as the weight
+        let direction = Onb::from_w(&rec.normal).local(&Vec3::rand_cosine_direction());
 
-        *scattered = Ray::new(rec.p, scatter_direction);
-        *attenuation = self.albedo;
+        *srec = ScatterRecord {
+            ray: Ray::new(rec.p, direction, r_in.time),
+            attenuation: self.albedo.value(rec.u, rec.v, &rec.p),
+            pdf: self.pdf(r_in, rec, &direction),
+            is_specular: false,
+        };
         true
     }
+
+    fn eval(&self, _r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let cosine = dot(&rec.normal, &normalized(*direction));
+        self.albedo.value(rec.u, rec.v, &rec.p) * (cosine.max(0.0) / PI)
+    }
+
+    fn pdf(&self, _r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        let cosine = dot(&rec.normal, &normalized(*direction));
+        cosine.max(0.0) / PI
+    }
 }
 
 impl Lambertian {
     pub fn new(r: f64, g: f64, b: f64) -> Lambertian {
         Lambertian {
-            albedo: Color::new(r, g, b),
+            albedo: Arc::new(SolidColor::new(r, g, b)),
         }
     }
+
+    pub fn textured(albedo: Arc<dyn Texture>) -> Lambertian {
+        Lambertian { albedo }
+    }
 }
 
 pub struct Metal {
-    pub albedo: Color,
+    pub albedo: Arc<dyn Texture>,
     pub fuzz: f64,
 }
 
 impl Material for Metal {
-    fn scatter(
-        &self,
-        r_in: &Ray,
-        rec: &HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
-    ) -> bool {
+    // Fuzzed reflections are still treated as specular, since the fuzz
+    // doesn't have a density that's cheap to evaluate
+    fn sample(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
         let reflected = normalized(r_in.dir).reflect(rec.normal);
-        *scattered = Ray::new(rec.p, reflected + Vec3::rand_in_sphere() * self.fuzz);
-        *attenuation = self.albedo;
+        *srec = ScatterRecord {
+            ray: Ray::new(
+                rec.p,
+                reflected + Vec3::rand_in_sphere() * self.fuzz,
+                r_in.time,
+            ),
+            attenuation: self.albedo.value(rec.u, rec.v, &rec.p),
+            pdf: 0.0,
+            is_specular: true,
+        };
 
         // Don't scatter if inside
-        dot(&scattered.dir, &rec.normal) > 0.0
+        dot(&srec.ray.dir, &rec.normal) > 0.0
     }
 }
 
 impl Metal {
     pub fn new(r: f64, g: f64, b: f64, fuzz: f64) -> Metal {
         Metal {
-            albedo: Color::new(r, g, b),
+            albedo: Arc::new(SolidColor::new(r, g, b)),
             fuzz,
         }
     }
+
+    pub fn textured(albedo: Arc<dyn Texture>, fuzz: f64) -> Metal {
+        Metal { albedo, fuzz }
+    }
 }
 
 pub struct Dielectric {
@@ -79,13 +150,7 @@ pub struct Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(
-        &self,
-        r_in: &Ray,
-        rec: &HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
-    ) -> bool {
+    fn sample(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
         // Air's index of refraction is 1. Ratio depends on if ray is
         // hitting the object from inside (mat -> air) or outside (air -> mat)
         let ir_ratio = if rec.front_face {
@@ -99,19 +164,22 @@ impl Material for Dielectric {
         // Check for total internal reflection
         let cos_theta = dot(&-unit_dir, &rec.normal).min(1.0);
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
-        let direction;
-
-        if ir_ratio * sin_theta > 1.0 || Dielectric::reflectance(cos_theta, ir_ratio) > rand_unit()
+        let direction = if ir_ratio * sin_theta > 1.0
+            || Dielectric::reflectance(cos_theta, ir_ratio) > rand_unit()
         {
             // Internal reflection
-            direction = unit_dir.reflect(rec.normal);
+            unit_dir.reflect(rec.normal)
         } else {
             // Refraction
-            direction = Vec3::refract(&unit_dir, &rec.normal, ir_ratio);
-        }
+            Vec3::refract(&unit_dir, &rec.normal, ir_ratio)
+        };
 
-        *scattered = Ray::new(rec.p, direction);
-        *attenuation = Color::new(1.0, 1.0, 1.0);
+        *srec = ScatterRecord {
+            ray: Ray::new(rec.p, direction, r_in.time),
+            attenuation: Color::new(1.0, 1.0, 1.0),
+            pdf: 0.0,
+            is_specular: true,
+        };
         true
     }
 }
@@ -128,3 +196,136 @@ impl Dielectric {
         r0s + (1.0 - r0s) * (1.0 - cos).powi(5)
     }
 }
+
+// Emits light from both sides of the surface and absorbs everything that hits it
+pub struct DiffuseLight {
+    pub emit: Color,
+}
+
+impl Material for DiffuseLight {
+    fn sample(&self, _r_in: &Ray, _rec: &HitRecord, _srec: &mut ScatterRecord) -> bool {
+        false
+    }
+
+    fn emitted(&self, _rec: &HitRecord) -> Color {
+        self.emit
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+}
+
+impl DiffuseLight {
+    pub fn new(r: f64, g: f64, b: f64) -> DiffuseLight {
+        DiffuseLight {
+            emit: Color::new(r, g, b),
+        }
+    }
+}
+
+// Phase function for volumes, scattering equally in every direction. There's
+// no surface, so unlike surface materials there's no cosine term
+pub struct Isotropic {
+    pub albedo: Arc<dyn Texture>,
+}
+
+impl Material for Isotropic {
+    fn sample(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        let direction = Vec3::rand_unit();
+        *srec = ScatterRecord {
+            ray: Ray::new(rec.p, direction, r_in.time),
+            attenuation: self.albedo.value(rec.u, rec.v, &rec.p),
+            pdf: self.pdf(r_in, rec, &direction),
+            is_specular: false,
+        };
+        true
+    }
+
+    fn eval(&self, _r_in: &Ray, rec: &HitRecord, _direction: &Vec3) -> Color {
+        self.albedo.value(rec.u, rec.v, &rec.p) / (4.0 * PI)
+    }
+
+    fn pdf(&self, _r_in: &Ray, _rec: &HitRecord, _direction: &Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn is_phase_function(&self) -> bool {
+        true
+    }
+}
+
+impl Isotropic {
+    pub fn new(r: f64, g: f64, b: f64) -> Isotropic {
+        Isotropic {
+            albedo: Arc::new(SolidColor::new(r, g, b)),
+        }
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture>) -> Isotropic {
+        Isotropic { albedo }
+    }
+}
+
+// Henyey-Greenstein phase function. g is the average cosine between the
+// incoming and scattered directions, positive values scatter forwards as
+// in clouds and negative ones backwards, while 0 is isotropic
+pub struct HenyeyGreenstein {
+    pub albedo: Arc<dyn Texture>,
+    pub g: f64,
+}
+
+impl HenyeyGreenstein {
+    pub fn textured(albedo: Arc<dyn Texture>, g: f64) -> HenyeyGreenstein {
+        HenyeyGreenstein { albedo, g }
+    }
+
+    fn phase(&self, cos_theta: f64) -> f64 {
+        let g = self.g;
+        let denom = 1.0 + g * g - 2.0 * g * cos_theta;
+        (1.0 - g * g) / (4.0 * PI * denom * denom.sqrt())
+    }
+}
+
+impl Material for HenyeyGreenstein {
+    fn sample(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        // Inverting the phase function's distribution over cos(theta),
+        // measured from the direction the ray was already travelling
+        let g = self.g;
+        let xi = rand_unit();
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * xi
+        } else {
+            let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * xi);
+            ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let phi = 2.0 * PI * rand_unit();
+        let direction = Onb::from_w(&r_in.dir).local(&Vec3::new(
+            phi.cos() * sin_theta,
+            phi.sin() * sin_theta,
+            cos_theta,
+        ));
+
+        // Sampling is exact, so only the albedo is left as the weight
+        *srec = ScatterRecord {
+            ray: Ray::new(rec.p, direction, r_in.time),
+            attenuation: self.albedo.value(rec.u, rec.v, &rec.p),
+            pdf: self.phase(cos_theta),
+            is_specular: false,
+        };
+        true
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        self.albedo.value(rec.u, rec.v, &rec.p) * self.pdf(r_in, rec, direction)
+    }
+
+    fn pdf(&self, r_in: &Ray, _rec: &HitRecord, direction: &Vec3) -> f64 {
+        self.phase(dot(&normalized(r_in.dir), &normalized(*direction)))
+    }
+
+    fn is_phase_function(&self) -> bool {
+        true
+    }
+}
@@ -1,6 +1,18 @@
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cell::RefCell;
 use std::f64::consts::PI;
 
+// Each thread draws from its own generator, which can be reseeded to make
+// renders reproducible
+thread_local! {
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
+pub fn seed_thread_rng(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
 pub fn deg_to_rad(degrees: f64) -> f64 {
     degrees * PI / 180.0
 }
@@ -16,9 +28,9 @@ pub fn clamp(x: f64, min: f64, max: f64) -> f64 {
 }
 
 pub fn rand_unit() -> f64 {
-    rand::thread_rng().gen_range(0.0..1.0)
+    RNG.with(|rng| rng.borrow_mut().gen_range(0.0..1.0))
 }
 
 pub fn rand_range(min: f64, max: f64) -> f64 {
-    rand::thread_rng().gen_range(min..max)
+    RNG.with(|rng| rng.borrow_mut().gen_range(min..max))
 }
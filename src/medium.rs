@@ -0,0 +1,121 @@
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hittable::*;
+use crate::material::{Isotropic, Material};
+use crate::math::rand_unit;
+use crate::ray::Ray;
+use crate::texture::Texture;
+use crate::vec3::*;
+
+// Fog filling the inside of a boundary object with uniform density. Rays
+// passing through are scattered at random distances, more often the denser
+// it is, so anything seen through it gets dimmer the further the ray has
+// to travel inside. The boundary has to be closed, and the ray is followed
+// through every stretch of it inside, so it needn't be convex
+pub struct ConstantMedium {
+    boundary: Arc<dyn Hittable>,
+    density: f64,
+    phase_function: Arc<dyn Material>,
+}
+
+impl ConstantMedium {
+    pub fn new(
+        boundary: Arc<dyn Hittable>,
+        density: f64,
+        albedo: Arc<dyn Texture>,
+    ) -> ConstantMedium {
+        ConstantMedium::with_phase(boundary, density, Arc::new(Isotropic::textured(albedo)))
+    }
+
+    pub fn with_phase(
+        boundary: Arc<dyn Hittable>,
+        density: f64,
+        phase_function: Arc<dyn Material>,
+    ) -> ConstantMedium {
+        ConstantMedium {
+            boundary,
+            density,
+            phase_function,
+        }
+    }
+
+    // Stretches of the ray inside the boundary, in order, clipped to the
+    // given interval
+    fn inside(&self, r: &Ray, t_min: f64, t_max: f64) -> Vec<(f64, f64)> {
+        let crossings = crossings(self.boundary.as_ref(), r, t_min);
+
+        // Starting inside means the first crossing is on the way out
+        let mut entered = match crossings.first() {
+            Some(first) if !first.front_face => Some(t_min),
+            _ => None,
+        };
+
+        let mut stretches = Vec::new();
+        for crossing in &crossings {
+            if crossing.t > t_max {
+                if let Some(t_enter) = entered.filter(|_| !crossing.front_face) {
+                    stretches.push((t_enter, t_max));
+                }
+                break;
+            }
+            if crossing.front_face {
+                entered = Some(crossing.t);
+            } else if let Some(t_enter) = entered.take() {
+                stretches.push((t_enter, crossing.t));
+            }
+        }
+        stretches
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let stretches = self.inside(r, t_min, t_max);
+        if stretches.is_empty() {
+            return false;
+        }
+
+        // Free flight distance, exponentially distributed, used up across
+        // the stretches in turn
+        let ray_length = r.dir.length();
+        let mut hit_distance = -rand_unit().ln() / self.density;
+        for (t_enter, t_exit) in stretches {
+            let distance_inside = (t_exit - t_enter) * ray_length;
+            if hit_distance <= distance_inside {
+                let t = t_enter + hit_distance / ray_length;
+                *rec = medium_hit(r, t, self.phase_function.clone());
+                return true;
+            }
+            hit_distance -= distance_inside;
+        }
+
+        false
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        self.boundary.bounding_box(output_box)
+    }
+
+    fn shadow_hit(&self, _r: &Ray, _t_min: f64, _t_max: f64, _rec: &mut HitRecord) -> bool {
+        false
+    }
+
+    // Beer-Lambert law, exact for a uniform density
+    fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64) -> f64 {
+        let inside: f64 = self
+            .inside(r, t_min, t_max)
+            .iter()
+            .map(|(t_enter, t_exit)| t_exit - t_enter)
+            .sum();
+        (-self.density * inside * r.dir.length()).exp()
+    }
+}
+
+// Scattering event at r(t) inside a volume. The normal is meaningless
+// there, phase functions ignore it
+pub fn medium_hit(r: &Ray, t: f64, phase_function: Arc<dyn Material>) -> HitRecord {
+    let mut rec = HitRecord::new(r.at(t), t, r, &Vec3::new(1.0, 0.0, 0.0), phase_function);
+    rec.front_face = true;
+    rec
+}
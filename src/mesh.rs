@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hittable::*;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::triangle::{intersect, random_on_triangle, triangle_bounds, triangle_pdf};
+use crate::vec3::*;
+
+// Indexed triangle mesh. Vertex attributes live in shared buffers and each
+// face holds indices into them, so normals and uvs are either empty or have
+// one entry per position
+pub struct TriangleMesh {
+    pub positions: Vec<Point3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f64, f64)>,
+    pub indices: Vec<[usize; 3]>,
+    pub mat: Arc<dyn Material>,
+}
+
+impl TriangleMesh {
+    pub fn new(
+        positions: Vec<Point3>,
+        normals: Vec<Vec3>,
+        uvs: Vec<(f64, f64)>,
+        indices: Vec<[usize; 3]>,
+        mat: Arc<dyn Material>,
+    ) -> Result<TriangleMesh, String> {
+        if !normals.is_empty() && normals.len() != positions.len() {
+            return Err(format!(
+                "{} normals for {} positions, expected one each or none",
+                normals.len(),
+                positions.len()
+            ));
+        }
+        if !uvs.is_empty() && uvs.len() != positions.len() {
+            return Err(format!(
+                "{} uvs for {} positions, expected one each or none",
+                uvs.len(),
+                positions.len()
+            ));
+        }
+        if let Some(i) = indices.iter().flatten().find(|&&i| i >= positions.len()) {
+            return Err(format!(
+                "face uses position {}, but there are {} positions",
+                i,
+                positions.len()
+            ));
+        }
+
+        Ok(TriangleMesh {
+            positions,
+            normals,
+            uvs,
+            indices,
+            mat,
+        })
+    }
+
+    // One hittable per face, all referring back to this mesh's buffers
+    pub fn triangles(mesh: Arc<TriangleMesh>) -> HittableList {
+        let mut list = HittableList::new();
+        for face in 0..mesh.indices.len() {
+            list.add(Arc::new(MeshTriangle {
+                mesh: mesh.clone(),
+                face,
+            }));
+        }
+        list
+    }
+}
+
+// Whether the faces of the meshes together enclose a solid, with every
+// edge shared by exactly two faces. Vertices are matched by position, since
+// files split them wherever normals or uvs change
+pub fn is_closed(meshes: &[Arc<TriangleMesh>]) -> bool {
+    let key = |p: Point3| [p.x.to_bits(), p.y.to_bits(), p.z.to_bits()];
+    let mut edges = HashMap::new();
+    for mesh in meshes {
+        for face in &mesh.indices {
+            for k in 0..3 {
+                let a = key(mesh.positions[face[k]]);
+                let b = key(mesh.positions[face[(k + 1) % 3]]);
+                *edges.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+            }
+        }
+    }
+    !edges.is_empty() && edges.values().all(|&count| count == 2)
+}
+
+pub struct MeshTriangle {
+    mesh: Arc<TriangleMesh>,
+    face: usize,
+}
+
+impl Hittable for MeshTriangle {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let mesh = &self.mesh;
+        let [i0, i1, i2] = mesh.indices[self.face];
+        let (p0, p1, p2) = (mesh.positions[i0], mesh.positions[i1], mesh.positions[i2]);
+
+        let (t, b1, b2) = match intersect(r, &p0, &p1, &p2, t_min, t_max) {
+            Some(hit) => hit,
+            None => return false,
+        };
+        let b0 = 1.0 - b1 - b2;
+
+        // Front face is decided by the true geometric normal
+        let geometric_norm = normalized(cross(&(p1 - p0), &(p2 - p0)));
+        *rec = HitRecord::new(r.at(t), t, r, &geometric_norm, mesh.mat.clone());
+        rec.barycentric = (b1, b2);
+
+        if !mesh.normals.is_empty() {
+            // Interpolated normal for smooth shading, flipped onto the same
+            // side as the geometric normal that HitRecord picked
+            let n = mesh.normals[i0] * b0 + mesh.normals[i1] * b1 + mesh.normals[i2] * b2;
+            if !n.near_zero() {
+                let shading_norm = normalized(n);
+                rec.normal = if dot(&shading_norm, &rec.normal) < 0.0 {
+                    -shading_norm
+                } else {
+                    shading_norm
+                };
+            }
+        }
+
+        if mesh.uvs.is_empty() {
+            rec.u = b1;
+            rec.v = b2;
+        } else {
+            let (uv0, uv1, uv2) = (mesh.uvs[i0], mesh.uvs[i1], mesh.uvs[i2]);
+            rec.u = uv0.0 * b0 + uv1.0 * b1 + uv2.0 * b2;
+            rec.v = uv0.1 * b0 + uv1.1 * b1 + uv2.1 * b2;
+        }
+
+        true
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        let [i0, i1, i2] = self.mesh.indices[self.face];
+        let positions = &self.mesh.positions;
+        *output_box = triangle_bounds(&positions[i0], &positions[i1], &positions[i2]);
+        true
+    }
+
+    fn pdf_value(&self, origin: &Point3, v: &Vec3) -> f64 {
+        let [i0, i1, i2] = self.mesh.indices[self.face];
+        let positions = &self.mesh.positions;
+        triangle_pdf(origin, v, &positions[i0], &positions[i1], &positions[i2])
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        let [i0, i1, i2] = self.mesh.indices[self.face];
+        let positions = &self.mesh.positions;
+        random_on_triangle(&positions[i0], &positions[i1], &positions[i2]) - *origin
+    }
+}
@@ -0,0 +1,205 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+use crate::vec3::*;
+
+const POINT_COUNT: usize = 256;
+
+// Gradient noise over 3D space, smoothly varying in roughly [-1, 1] with
+// features about one unit apart. Each seed gives a different, repeatable
+// pattern, independent of the render's own random numbers
+pub struct Perlin {
+    gradients: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Perlin {
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        let gradients = (0..POINT_COUNT)
+            .map(|_| loop {
+                let v = Vec3::new(
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                );
+                let len_sq = v.length_sq();
+                if len_sq > 1e-6 && len_sq < 1.0 {
+                    break normalized(v);
+                }
+            })
+            .collect();
+
+        let mut permutation = || {
+            let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+            p.shuffle(&mut rng);
+            p
+        };
+        let perm_x = permutation();
+        let perm_y = permutation();
+        let perm_z = permutation();
+
+        Perlin {
+            gradients,
+            perm_x,
+            perm_y,
+            perm_z,
+        }
+    }
+
+    pub fn noise(&self, p: &Point3) -> f64 {
+        let (fx, fy, fz) = (p.x.floor(), p.y.floor(), p.z.floor());
+        let (u, v, w) = (p.x - fx, p.y - fy, p.z - fz);
+        let (i, j, k) = (fx as i64, fy as i64, fz as i64);
+
+        // Gradients at the 8 corners of the surrounding lattice cell
+        let mut corners = [[[Vec3::new(0.0, 0.0, 0.0); 2]; 2]; 2];
+        for (di, plane) in corners.iter_mut().enumerate() {
+            for (dj, row) in plane.iter_mut().enumerate() {
+                for (dk, c) in row.iter_mut().enumerate() {
+                    let index = self.perm_x[((i + di as i64) & 255) as usize]
+                        ^ self.perm_y[((j + dj as i64) & 255) as usize]
+                        ^ self.perm_z[((k + dk as i64) & 255) as usize];
+                    *c = self.gradients[index];
+                }
+            }
+        }
+
+        // Hermite smoothing hides the lattice's grid lines
+        let uu = u * u * (3.0 - 2.0 * u);
+        let vv = v * v * (3.0 - 2.0 * v);
+        let ww = w * w * (3.0 - 2.0 * w);
+
+        let mut accum = 0.0;
+        for (di, plane) in corners.iter().enumerate() {
+            for (dj, row) in plane.iter().enumerate() {
+                for (dk, gradient) in row.iter().enumerate() {
+                    let (a, b, c) = (di as f64, dj as f64, dk as f64);
+                    let offset = Vec3::new(u - a, v - b, w - c);
+                    accum += (a * uu + (1.0 - a) * (1.0 - uu))
+                        * (b * vv + (1.0 - b) * (1.0 - vv))
+                        * (c * ww + (1.0 - c) * (1.0 - ww))
+                        * dot(gradient, &offset);
+                }
+            }
+        }
+
+        accum
+    }
+
+    // Fractal sum of octaves, each lacunarity times finer and gain times
+    // weaker than the last
+    pub fn fbm(&self, p: &Point3, octaves: u32, lacunarity: f64, gain: f64) -> f64 {
+        self.octaves(p, octaves, lacunarity, gain, |n| n)
+    }
+
+    // Like fbm but summing the absolute value of each octave, which gives
+    // billowy, turbulent looking results that are never negative
+    pub fn turbulence(&self, p: &Point3, octaves: u32, lacunarity: f64, gain: f64) -> f64 {
+        self.octaves(p, octaves, lacunarity, gain, f64::abs)
+    }
+
+    fn octaves(
+        &self,
+        p: &Point3,
+        octaves: u32,
+        lacunarity: f64,
+        gain: f64,
+        shape: fn(f64) -> f64,
+    ) -> f64 {
+        let mut accum = 0.0;
+        let mut frequency = 1.0;
+        let mut weight = 1.0;
+
+        for _ in 0..octaves {
+            accum += weight * shape(self.noise(&(*p * frequency)));
+            frequency *= lacunarity;
+            weight *= gain;
+        }
+
+        accum
+    }
+}
+
+// Cellular noise. Space is split into unit cells each holding one random
+// feature point, and the noise is the distance to the nearest ones
+pub struct Worley {
+    seed: u64,
+}
+
+impl Worley {
+    pub fn new(seed: u64) -> Worley {
+        Worley { seed }
+    }
+
+    // Distances to the closest and second closest feature points
+    pub fn distances(&self, p: &Point3) -> (f64, f64) {
+        let cell = (p.x.floor() as i64, p.y.floor() as i64, p.z.floor() as i64);
+        let mut f1 = f64::INFINITY;
+        let mut f2 = f64::INFINITY;
+
+        // Closest any point in a cell can be to p
+        let reach = |c: (i64, i64, i64)| {
+            let axis = |p: f64, c: i64| (c as f64 - p).max(p - (c + 1) as f64).max(0.0);
+            Vec3::new(axis(p.x, c.0), axis(p.y, c.1), axis(p.z, c.2)).length()
+        };
+
+        // Search rings of cells further and further out, until every cell
+        // left is too far away to hold one of the two nearest points
+        for ring in 0i64.. {
+            if (ring - 1) as f64 >= f2 {
+                break;
+            }
+            for dx in -ring..=ring {
+                for dy in -ring..=ring {
+                    for dz in -ring..=ring {
+                        if dx.abs().max(dy.abs()).max(dz.abs()) != ring {
+                            continue;
+                        }
+                        let c = (cell.0 + dx, cell.1 + dy, cell.2 + dz);
+                        if reach(c) >= f2 {
+                            continue;
+                        }
+                        let d = (self.feature_point(c) - *p).length();
+                        if d < f1 {
+                            f2 = f1;
+                            f1 = d;
+                        } else if d < f2 {
+                            f2 = d;
+                        }
+                    }
+                }
+            }
+        }
+
+        (f1, f2)
+    }
+
+    // Hashing the cell coordinates, rather than storing points, keeps the
+    // pattern infinite
+    fn feature_point(&self, cell: (i64, i64, i64)) -> Point3 {
+        let mut h = self.seed;
+        for c in [cell.0, cell.1, cell.2] {
+            h = splitmix64(h ^ c as u64);
+        }
+
+        let mut coord = || {
+            h = splitmix64(h);
+            (h >> 11) as f64 / (1u64 << 53) as f64
+        };
+        let (x, y, z) = (coord(), coord(), coord());
+
+        Point3::new(cell.0 as f64 + x, cell.1 as f64 + y, cell.2 as f64 + z)
+    }
+}
+
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,473 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::hittable::HittableList;
+use crate::material::*;
+use crate::mesh::TriangleMesh;
+use crate::texture::{ImageTexture, SolidColor, Texture};
+use crate::vec3::*;
+
+#[derive(Debug)]
+pub struct ObjError {
+    pub path: PathBuf,
+    // None for errors that aren't tied to a line, e.g. a missing file
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.path.display(), line, self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+// Tracks where we are in a file so every error can point at its line
+struct LineContext<'a> {
+    path: &'a Path,
+    line: usize,
+}
+
+impl LineContext<'_> {
+    fn error(&self, message: String) -> ObjError {
+        ObjError {
+            path: self.path.to_path_buf(),
+            line: Some(self.line),
+            message,
+        }
+    }
+
+    fn floats(
+        &self,
+        keyword: &str,
+        args: &[&str],
+        min: usize,
+        max: usize,
+    ) -> Result<Vec<f64>, ObjError> {
+        if args.len() < min || args.len() > max {
+            let expected = if min == max {
+                format!("{}", min)
+            } else {
+                format!("{} to {}", min, max)
+            };
+            return Err(self.error(format!(
+                "'{}' expects {} numbers, found {}",
+                keyword,
+                expected,
+                args.len()
+            )));
+        }
+
+        args.iter()
+            .map(|a| {
+                a.parse::<f64>()
+                    .map_err(|_| self.error(format!("'{}' has invalid number '{}'", keyword, a)))
+            })
+            .collect()
+    }
+
+    fn color(&self, keyword: &str, args: &[&str]) -> Result<Color, ObjError> {
+        // A single value is shorthand for a grey
+        let c = self.floats(keyword, args, 1, 3)?;
+        match c.len() {
+            1 => Ok(Color::new(c[0], c[0], c[0])),
+            3 => Ok(Color::new(c[0], c[1], c[2])),
+            _ => Err(self.error(format!("'{}' expects 1 or 3 numbers", keyword))),
+        }
+    }
+}
+
+fn read_file(path: &Path) -> Result<String, ObjError> {
+    fs::read_to_string(path).map_err(|why| ObjError {
+        path: path.to_path_buf(),
+        line: None,
+        message: format!("could not read file: {}", why),
+    })
+}
+
+// Material parameters as written in a .mtl file, before being mapped onto
+// one of our materials
+struct MtlEntry {
+    diffuse: Color,
+    diffuse_map: Option<Arc<dyn Texture>>,
+    emission: Color,
+    specular: Color,
+    shininess: f64,
+    dissolve: f64,
+    ior: f64,
+    illum: u32,
+}
+
+impl MtlEntry {
+    fn new() -> MtlEntry {
+        MtlEntry {
+            diffuse: Color::new(0.8, 0.8, 0.8),
+            diffuse_map: None,
+            emission: Color::new(0.0, 0.0, 0.0),
+            specular: Color::new(0.0, 0.0, 0.0),
+            shininess: 0.0,
+            dissolve: 1.0,
+            ior: 1.5,
+            illum: 2,
+        }
+    }
+
+    // Emissive entries become lights, transparent entries become glass,
+    // entries with reflection turned on (illum 3) or with only a specular
+    // color become metal, and everything else is diffuse. A diffuse map
+    // replaces Kd rather than being tinted by it
+    fn to_material(&self) -> Arc<dyn Material> {
+        let max = |c: &Color| c.x.max(c.y).max(c.z);
+
+        if max(&self.emission) > 0.0 {
+            Arc::new(DiffuseLight {
+                emit: self.emission,
+            })
+        } else if self.dissolve < 1.0 || matches!(self.illum, 4 | 6 | 7 | 9) {
+            Arc::new(Dielectric::new(self.ior))
+        } else if max(&self.specular) > 0.0 && (self.illum == 3 || max(&self.diffuse) == 0.0) {
+            // Map the Phong exponent onto fuzz so glossier surfaces are sharper
+            Arc::new(Metal::textured(
+                Arc::new(SolidColor {
+                    color: self.specular,
+                }),
+                (2.0 / (self.shininess + 2.0)).sqrt().min(1.0),
+            ))
+        } else {
+            Arc::new(Lambertian::textured(match &self.diffuse_map {
+                Some(map) => map.clone(),
+                None => Arc::new(SolidColor {
+                    color: self.diffuse,
+                }),
+            }))
+        }
+    }
+}
+
+pub fn load_mtl(path: &Path) -> Result<HashMap<String, Arc<dyn Material>>, ObjError> {
+    let source = read_file(path)?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut entries: Vec<(String, MtlEntry)> = Vec::new();
+
+    for (i, raw_line) in source.lines().enumerate() {
+        let ctx = LineContext { path, line: i + 1 };
+        let line = raw_line.split('#').next().unwrap().trim();
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(k) => k,
+            None => continue,
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        if keyword == "newmtl" {
+            if args.len() != 1 {
+                return Err(ctx.error("'newmtl' expects a single name".to_string()));
+            }
+            entries.push((args[0].to_string(), MtlEntry::new()));
+            continue;
+        }
+
+        let entry = match entries.last_mut() {
+            Some((_, entry)) => entry,
+            None => {
+                return Err(ctx.error(format!("'{}' appears before any 'newmtl'", keyword)));
+            }
+        };
+
+        match keyword {
+            "Kd" => entry.diffuse = ctx.color(keyword, &args)?,
+            "Ks" => entry.specular = ctx.color(keyword, &args)?,
+            "Ke" => entry.emission = ctx.color(keyword, &args)?,
+            "Ns" => entry.shininess = ctx.floats(keyword, &args, 1, 1)?[0],
+            "Ni" => entry.ior = ctx.floats(keyword, &args, 1, 1)?[0],
+            "d" => entry.dissolve = ctx.floats(keyword, &args, 1, 1)?[0],
+            "Tr" => entry.dissolve = 1.0 - ctx.floats(keyword, &args, 1, 1)?[0],
+            "illum" => {
+                entry.illum = match args.as_slice() {
+                    [n] => n
+                        .parse()
+                        .map_err(|_| ctx.error(format!("'illum' has invalid model '{}'", n)))?,
+                    _ => return Err(ctx.error("'illum' expects a single model number".to_string())),
+                }
+            }
+            "map_Kd" => {
+                // Options like -s or -o come before the file name, which
+                // is assumed to be last and free of spaces
+                let file = match args.last() {
+                    Some(file) => file,
+                    None => return Err(ctx.error("'map_Kd' expects a file name".to_string())),
+                };
+                let texture = ImageTexture::load(&dir.join(file)).map_err(|why| ctx.error(why))?;
+                entry.diffuse_map = Some(Arc::new(texture));
+            }
+            // Ambient, other texture maps etc. have no equivalent yet
+            _ => {}
+        }
+    }
+
+    Ok(entries
+        .into_iter()
+        .map(|(name, entry)| (name, entry.to_material()))
+        .collect())
+}
+
+// Faces are grouped by material, with a mesh built per group. OBJ indexes
+// positions, uvs and normals separately, so each distinct combination used
+// by a face corner becomes one mesh vertex
+struct MeshBuilder {
+    positions: Vec<Point3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f64, f64)>,
+    indices: Vec<[usize; 3]>,
+    vertex_map: HashMap<(usize, Option<usize>, Option<usize>), usize>,
+    missing_normals: bool,
+    missing_uvs: bool,
+}
+
+impl MeshBuilder {
+    fn new() -> MeshBuilder {
+        MeshBuilder {
+            positions: Vec::new(),
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            indices: Vec::new(),
+            vertex_map: HashMap::new(),
+            missing_normals: false,
+            missing_uvs: false,
+        }
+    }
+
+    fn vertex(&mut self, corner: (usize, Option<usize>, Option<usize>), obj: &ObjData) -> usize {
+        if let Some(&index) = self.vertex_map.get(&corner) {
+            return index;
+        }
+
+        let (v, vt, vn) = corner;
+        let index = self.positions.len();
+        self.positions.push(obj.positions[v]);
+        self.uvs.push(vt.map_or((0.0, 0.0), |i| obj.uvs[i]));
+        self.normals
+            .push(vn.map_or(Vec3::new(0.0, 0.0, 0.0), |i| obj.normals[i]));
+        self.missing_uvs |= vt.is_none();
+        self.missing_normals |= vn.is_none();
+        self.vertex_map.insert(corner, index);
+
+        index
+    }
+
+    fn build(self, mat: Arc<dyn Material>) -> Result<TriangleMesh, String> {
+        // Partially specified attributes can't be interpolated consistently,
+        // so fall back to flat shading and barycentric uvs
+        let normals = if self.missing_normals {
+            Vec::new()
+        } else {
+            self.normals
+        };
+        let uvs = if self.missing_uvs {
+            Vec::new()
+        } else {
+            self.uvs
+        };
+
+        TriangleMesh::new(self.positions, normals, uvs, self.indices, mat)
+    }
+}
+
+struct ObjData {
+    positions: Vec<Point3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f64, f64)>,
+}
+
+// Resolves a 1-based (or negative, relative to the end) OBJ index
+fn resolve_index(
+    ctx: &LineContext,
+    token: &str,
+    len: usize,
+    kind: &str,
+) -> Result<usize, ObjError> {
+    let index: i64 = token
+        .parse()
+        .map_err(|_| ctx.error(format!("invalid {} index '{}'", kind, token)))?;
+
+    let resolved = if index > 0 {
+        index - 1
+    } else {
+        len as i64 + index
+    };
+
+    if index == 0 || resolved < 0 || resolved >= len as i64 {
+        return Err(ctx.error(format!(
+            "{} index {} is out of range, {} defined so far",
+            kind, index, len
+        )));
+    }
+
+    Ok(resolved as usize)
+}
+
+fn parse_corner(
+    ctx: &LineContext,
+    token: &str,
+    obj: &ObjData,
+) -> Result<(usize, Option<usize>, Option<usize>), ObjError> {
+    // v, v/vt, v//vn or v/vt/vn
+    let parts: Vec<&str> = token.split('/').collect();
+    if parts.is_empty() || parts.len() > 3 {
+        return Err(ctx.error(format!("malformed face vertex '{}'", token)));
+    }
+
+    let v = resolve_index(ctx, parts[0], obj.positions.len(), "vertex")?;
+    let vt = match parts.get(1) {
+        Some(t) if !t.is_empty() => {
+            Some(resolve_index(ctx, t, obj.uvs.len(), "texture coordinate")?)
+        }
+        _ => None,
+    };
+    let vn = match parts.get(2) {
+        Some(n) if !n.is_empty() => Some(resolve_index(ctx, n, obj.normals.len(), "normal")?),
+        _ => None,
+    };
+
+    Ok((v, vt, vn))
+}
+
+// Loads every face in an OBJ file into a list of triangles. Faces before
+// any 'usemtl' (or when the file has no material library) use default_mat
+pub fn load_obj(path: &Path, default_mat: Arc<dyn Material>) -> Result<HittableList, ObjError> {
+    let mut list = HittableList::new();
+    for mesh in load_obj_meshes(path, default_mat)? {
+        list.objects.extend(TriangleMesh::triangles(mesh).objects);
+    }
+
+    Ok(list)
+}
+
+// Same as load_obj, but keeps the meshes whole with one per material used
+pub fn load_obj_meshes(
+    path: &Path,
+    default_mat: Arc<dyn Material>,
+) -> Result<Vec<Arc<TriangleMesh>>, ObjError> {
+    let source = read_file(path)?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut obj = ObjData {
+        positions: Vec::new(),
+        normals: Vec::new(),
+        uvs: Vec::new(),
+    };
+    let mut materials: HashMap<String, Arc<dyn Material>> = HashMap::new();
+
+    // Builders are keyed by material name, None being the default material
+    let mut groups: Vec<(Option<String>, MeshBuilder)> = vec![(None, MeshBuilder::new())];
+    let mut current = 0;
+
+    for (i, raw_line) in source.lines().enumerate() {
+        let ctx = LineContext { path, line: i + 1 };
+        let line = raw_line.split('#').next().unwrap().trim();
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(k) => k,
+            None => continue,
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        match keyword {
+            "v" => {
+                // Optional w component is ignored
+                let p = ctx.floats(keyword, &args, 3, 4)?;
+                obj.positions.push(Point3::new(p[0], p[1], p[2]));
+            }
+            "vn" => {
+                let n = ctx.floats(keyword, &args, 3, 3)?;
+                let n = Vec3::new(n[0], n[1], n[2]);
+                if n.near_zero() {
+                    return Err(ctx.error("'vn' has zero length".to_string()));
+                }
+                obj.normals.push(normalized(n));
+            }
+            "vt" => {
+                let t = ctx.floats(keyword, &args, 1, 3)?;
+                obj.uvs.push((t[0], t.get(1).copied().unwrap_or(0.0)));
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(ctx.error(format!(
+                        "face needs at least 3 vertices, found {}",
+                        args.len()
+                    )));
+                }
+
+                let corners = args
+                    .iter()
+                    .map(|a| parse_corner(&ctx, a, &obj))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                // Fan triangulation, which is fine for the convex polygons
+                // exporters write
+                let builder = &mut groups[current].1;
+                let first = builder.vertex(corners[0], &obj);
+                for pair in corners[1..].windows(2) {
+                    let b = builder.vertex(pair[0], &obj);
+                    let c = builder.vertex(pair[1], &obj);
+                    builder.indices.push([first, b, c]);
+                }
+            }
+            "mtllib" => {
+                if args.is_empty() {
+                    return Err(ctx.error("'mtllib' expects a file name".to_string()));
+                }
+                for lib in &args {
+                    materials.extend(load_mtl(&dir.join(lib))?);
+                }
+            }
+            "usemtl" => {
+                if args.len() != 1 {
+                    return Err(ctx.error("'usemtl' expects a single name".to_string()));
+                }
+                let name = args[0];
+                if !materials.contains_key(name) {
+                    return Err(ctx.error(format!("unknown material '{}'", name)));
+                }
+
+                current = match groups.iter().position(|(n, _)| n.as_deref() == Some(name)) {
+                    Some(index) => index,
+                    None => {
+                        groups.push((Some(name.to_string()), MeshBuilder::new()));
+                        groups.len() - 1
+                    }
+                };
+            }
+            // Grouping, smoothing groups, lines, points and free-form
+            // surfaces don't affect rendering
+            _ => {}
+        }
+    }
+
+    let mut meshes = Vec::new();
+    for (name, builder) in groups {
+        if builder.indices.is_empty() {
+            continue;
+        }
+
+        let mat = match name {
+            Some(name) => materials[&name].clone(),
+            None => default_mat.clone(),
+        };
+        let mesh = builder.build(mat).map_err(|message| ObjError {
+            path: path.to_path_buf(),
+            line: None,
+            message,
+        })?;
+        meshes.push(Arc::new(mesh));
+    }
+
+    Ok(meshes)
+}
@@ -0,0 +1,29 @@
+use crate::vec3::*;
+
+// Orthonormal basis built around a single direction, used to turn samples
+// generated around +z into samples around an arbitrary axis
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    pub fn from_w(n: &Vec3) -> Onb {
+        let w = normalized(*n);
+        // Any vector not parallel to w works as a starting point
+        let a = if w.x.abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = normalized(cross(&w, &a));
+        let u = cross(&w, &v);
+
+        Onb { u, v, w }
+    }
+
+    pub fn local(&self, a: &Vec3) -> Vec3 {
+        self.u * a.x + self.v * a.y + self.w * a.z
+    }
+}
@@ -0,0 +1,344 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::color::{tone_map, ToneMap};
+use crate::vec3::Color;
+
+// Finished image with rows stored top to bottom, holding the linear
+// radiance of each pixel. Tone mapping only happens when writing to a low
+// dynamic range format
+pub struct Framebuffer {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Framebuffer {
+        Framebuffer {
+            width,
+            height,
+            pixels: vec![Color::new(0.0, 0.0, 0.0); width * height],
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, c: Color) {
+        self.pixels[y * self.width + x] = c;
+    }
+}
+
+// Bits per channel. Integer formats use 8 or 16, floating point formats
+// use 16 for half and 32 for single precision
+#[derive(Clone, Copy, PartialEq)]
+pub enum BitDepth {
+    Eight,
+    Sixteen,
+    ThirtyTwo,
+}
+
+impl BitDepth {
+    // Largest integer sample, integer formats are only ever 8 or 16 bit
+    fn max_value(&self) -> u32 {
+        match self {
+            BitDepth::Eight => 255,
+            _ => 65535,
+        }
+    }
+
+    fn bits(&self) -> u32 {
+        match self {
+            BitDepth::Eight => 8,
+            BitDepth::Sixteen => 16,
+            BitDepth::ThirtyTwo => 32,
+        }
+    }
+
+    // Maps [0, 1] evenly onto the available integer values
+    fn quantize(&self, x: f64) -> u32 {
+        let levels = self.max_value() as f64 + 1.0;
+        ((x.clamp(0.0, 1.0) * levels) as u32).min(self.max_value())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ImageFormat {
+    Png,
+    // Binary P6
+    Ppm,
+    // Plain text P3, mostly for debugging
+    PpmAscii,
+    // OpenEXR, half or single precision
+    Exr,
+    // Radiance RGBE
+    Hdr,
+    // Portable float map
+    Pfm,
+}
+
+impl ImageFormat {
+    // Picks the encoder from the file extension. ascii_ppm selects P3 over
+    // the default binary P6 for .ppm files
+    pub fn from_path(path: &Path, ascii_ppm: bool) -> Result<ImageFormat, String> {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("png") => Ok(ImageFormat::Png),
+            Some("ppm") if ascii_ppm => Ok(ImageFormat::PpmAscii),
+            Some("ppm") => Ok(ImageFormat::Ppm),
+            Some("exr") => Ok(ImageFormat::Exr),
+            Some("hdr") => Ok(ImageFormat::Hdr),
+            Some("pfm") => Ok(ImageFormat::Pfm),
+            Some(other) => Err(format!(
+                "unsupported output format '.{}', use one of {}",
+                other, SUPPORTED
+            )),
+            None => Err(format!(
+                "can't tell the output format of '{}' without an extension, use one of {}",
+                path.display(),
+                SUPPORTED
+            )),
+        }
+    }
+
+    // Checks a requested bit depth against what the format can store,
+    // picking the format's natural depth if none was requested
+    pub fn bit_depth(&self, requested: Option<BitDepth>) -> Result<BitDepth, String> {
+        let (default, allowed): (BitDepth, &[BitDepth]) = match self {
+            ImageFormat::Png | ImageFormat::Ppm | ImageFormat::PpmAscii => {
+                (BitDepth::Eight, &[BitDepth::Eight, BitDepth::Sixteen])
+            }
+            ImageFormat::Exr => (
+                BitDepth::ThirtyTwo,
+                &[BitDepth::Sixteen, BitDepth::ThirtyTwo],
+            ),
+            // RGBE packs a shared exponent rather than per channel bits
+            ImageFormat::Hdr | ImageFormat::Pfm => (BitDepth::ThirtyTwo, &[BitDepth::ThirtyTwo]),
+        };
+
+        match requested {
+            None => Ok(default),
+            Some(depth) if allowed.contains(&depth) => Ok(depth),
+            Some(depth) => Err(format!(
+                "{} bit output isn't supported by this format, use {}",
+                depth.bits(),
+                allowed
+                    .iter()
+                    .map(|d| d.bits().to_string())
+                    .collect::<Vec<_>>()
+                    .join(" or ")
+            )),
+        }
+    }
+
+    pub fn is_hdr(&self) -> bool {
+        matches!(self, ImageFormat::Exr | ImageFormat::Hdr | ImageFormat::Pfm)
+    }
+}
+
+const SUPPORTED: &str = ".png, .ppm, .exr, .hdr or .pfm";
+
+// How the linear framebuffer gets encoded. Tone mapping settings only apply
+// to low dynamic range formats
+#[derive(Clone, Copy)]
+pub struct EncodeSettings {
+    pub bit_depth: BitDepth,
+    pub tone_map: ToneMap,
+    pub exposure: f64,
+}
+
+pub fn write_image(
+    path: &Path,
+    image: &Framebuffer,
+    format: ImageFormat,
+    settings: &EncodeSettings,
+) -> io::Result<()> {
+    if format == ImageFormat::Exr {
+        // The exr crate manages its own file
+        return write_exr(path, image, settings.bit_depth);
+    }
+
+    let mut out = BufWriter::new(File::create(path)?);
+
+    if format.is_hdr() {
+        match format {
+            ImageFormat::Hdr => write_hdr(&mut out, image)?,
+            _ => write_pfm(&mut out, image)?,
+        }
+    } else {
+        let display = tone_mapped(image, settings);
+        let depth = settings.bit_depth;
+        match format {
+            ImageFormat::Png => write_png(&mut out, &display, depth)?,
+            ImageFormat::Ppm => write_ppm(&mut out, &display, depth)?,
+            _ => write_ppm_ascii(&mut out, &display, depth)?,
+        }
+    }
+
+    out.flush()
+}
+
+fn tone_mapped(image: &Framebuffer, settings: &EncodeSettings) -> Framebuffer {
+    Framebuffer {
+        width: image.width,
+        height: image.height,
+        pixels: image
+            .pixels
+            .iter()
+            .map(|c| tone_map(c, settings.tone_map, settings.exposure))
+            .collect(),
+    }
+}
+
+fn write_exr(path: &Path, image: &Framebuffer, depth: BitDepth) -> io::Result<()> {
+    let result = if depth == BitDepth::Sixteen {
+        exr::prelude::write_rgb_file(path, image.width, image.height, |x, y| {
+            let c = image.get(x, y);
+            (
+                exr::prelude::f16::from_f64(c.x),
+                exr::prelude::f16::from_f64(c.y),
+                exr::prelude::f16::from_f64(c.z),
+            )
+        })
+    } else {
+        exr::prelude::write_rgb_file(path, image.width, image.height, |x, y| {
+            let c = image.get(x, y);
+            (c.x as f32, c.y as f32, c.z as f32)
+        })
+    };
+
+    result.map_err(io::Error::other)
+}
+
+fn write_hdr<W: Write>(out: &mut W, image: &Framebuffer) -> io::Result<()> {
+    // Scanlines are written flat rather than run length encoded, which every
+    // reader has to accept
+    let header = format!(
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        image.height, image.width
+    );
+    out.write_all(header.as_bytes())?;
+
+    for c in &image.pixels {
+        out.write_all(&to_rgbe(c))?;
+    }
+
+    Ok(())
+}
+
+// Radiance's shared exponent encoding, three 8 bit mantissas scaled by the
+// largest channel's power of two
+fn to_rgbe(c: &Color) -> [u8; 4] {
+    let r = c.x.max(0.0);
+    let g = c.y.max(0.0);
+    let b = c.z.max(0.0);
+    let v = r.max(g).max(b);
+
+    if v < 1e-32 || !v.is_finite() {
+        return [0, 0, 0, 0];
+    }
+
+    // v = m * 2^e with m in [0.5, 1)
+    let e = v.log2().floor() as i32 + 1;
+    let scale = 256.0 / 2f64.powi(e);
+
+    [
+        (r * scale).min(255.0) as u8,
+        (g * scale).min(255.0) as u8,
+        (b * scale).min(255.0) as u8,
+        (e + 128).clamp(0, 255) as u8,
+    ]
+}
+
+fn write_pfm<W: Write>(out: &mut W, image: &Framebuffer) -> io::Result<()> {
+    // Negative scale marks the data as little endian. Rows go bottom to top
+    let header = format!("PF\n{} {}\n-1.0\n", image.width, image.height);
+    out.write_all(header.as_bytes())?;
+
+    let mut row_bytes = Vec::with_capacity(image.width * 12);
+    for y in (0..image.height).rev() {
+        row_bytes.clear();
+        for x in 0..image.width {
+            let c = image.get(x, y);
+            for v in [c.x, c.y, c.z] {
+                row_bytes.extend_from_slice(&(v as f32).to_le_bytes());
+            }
+        }
+        out.write_all(&row_bytes)?;
+    }
+
+    Ok(())
+}
+
+fn write_png<W: Write>(out: &mut W, image: &Framebuffer, depth: BitDepth) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(out, image.width as u32, image.height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(match depth {
+        BitDepth::Eight => png::BitDepth::Eight,
+        _ => png::BitDepth::Sixteen,
+    });
+
+    // PNG stores 16 bit samples big endian, same as PPM
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&raster_bytes(image, depth))?;
+    writer.finish()?;
+
+    Ok(())
+}
+
+fn write_ppm<W: Write>(out: &mut W, image: &Framebuffer, depth: BitDepth) -> io::Result<()> {
+    let header = format!(
+        "P6\n{} {}\n{}\n",
+        image.width,
+        image.height,
+        depth.max_value()
+    );
+    out.write_all(header.as_bytes())?;
+    out.write_all(&raster_bytes(image, depth))
+}
+
+fn write_ppm_ascii<W: Write>(out: &mut W, image: &Framebuffer, depth: BitDepth) -> io::Result<()> {
+    let header = format!(
+        "P3\n{} {}\n{}\n",
+        image.width,
+        image.height,
+        depth.max_value()
+    );
+    out.write_all(header.as_bytes())?;
+
+    for c in &image.pixels {
+        writeln!(
+            out,
+            "{} {} {}",
+            depth.quantize(c.x),
+            depth.quantize(c.y),
+            depth.quantize(c.z)
+        )?;
+    }
+
+    Ok(())
+}
+
+// Interleaved RGB samples, big endian when 16 bit
+fn raster_bytes(image: &Framebuffer, depth: BitDepth) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(image.pixels.len() * 6);
+
+    for c in &image.pixels {
+        for x in [c.x, c.y, c.z] {
+            let value = depth.quantize(x);
+            match depth {
+                BitDepth::Eight => bytes.push(value as u8),
+                _ => bytes.extend_from_slice(&(value as u16).to_be_bytes()),
+            }
+        }
+    }
+
+    bytes
+}
@@ -0,0 +1,81 @@
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hittable::*;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vec3::*;
+
+// Infinite plane through point, facing along normal. It has no bounding
+// box, so BVHs keep it to one side and test it against every ray
+pub struct Plane {
+    point: Point3,
+    normal: Vec3,
+    mat: Arc<dyn Material>,
+    // Directions in the plane that uvs are measured along
+    u_axis: Vec3,
+    v_axis: Vec3,
+    // Distance over which uvs go from 0 to 1
+    uv_scale: f64,
+}
+
+impl Plane {
+    pub fn new(point: Point3, normal: Vec3, mat: Arc<dyn Material>) -> Plane {
+        let normal = normalized(normal);
+
+        // Pick uv axes so images read the right way round when looking at
+        // the front of the plane, with v running up walls and along -z on
+        // floors, so a floor facing up gets u along x
+        let up = if normal.y.abs() < 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(0.0, 0.0, -1.0)
+        };
+        let u_axis = normalized(cross(&up, &normal));
+        let v_axis = cross(&normal, &u_axis);
+
+        Plane {
+            point,
+            normal,
+            mat,
+            u_axis,
+            v_axis,
+            uv_scale: 1.0,
+        }
+    }
+
+    pub fn with_uv_scale(mut self, uv_scale: f64) -> Plane {
+        self.uv_scale = uv_scale;
+        self
+    }
+}
+
+impl Hittable for Plane {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        // Ray is parallel to the plane
+        let denom = dot(&self.normal, &r.dir);
+        if denom.abs() < 1e-8 {
+            return false;
+        }
+
+        let t = dot(&self.normal, &(self.point - r.origin)) / denom;
+        if t < t_min || t > t_max {
+            return false;
+        }
+
+        let p = r.at(t);
+        *rec = HitRecord::new(p, t, r, &self.normal, self.mat.clone());
+
+        // Uvs keep growing across the plane, textures that look them up
+        // wrap them to repeat
+        let offset = p - self.point;
+        rec.u = dot(&offset, &self.u_axis) / self.uv_scale;
+        rec.v = dot(&offset, &self.v_axis) / self.uv_scale;
+
+        true
+    }
+
+    fn bounding_box(&self, _output_box: &mut Aabb) -> bool {
+        false
+    }
+}
@@ -0,0 +1,152 @@
+use std::f64::consts::PI;
+
+// Real roots of a polynomial, in increasing order
+#[derive(Clone, Copy)]
+pub struct Roots {
+    values: [f64; 4],
+    len: usize,
+}
+
+impl Roots {
+    fn new() -> Roots {
+        Roots {
+            values: [0.0; 4],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, x: f64) {
+        if x.is_finite() {
+            self.values[self.len] = x;
+            self.len += 1;
+        }
+    }
+
+    fn sorted(mut self) -> Roots {
+        self.values[..self.len].sort_by(f64::total_cmp);
+        self
+    }
+
+    pub fn as_slice(&self) -> &[f64] {
+        &self.values[..self.len]
+    }
+}
+
+// a x^2 + b x + c = 0. Falls back to the linear equation when a is zero,
+// and avoids subtracting nearly equal numbers when b dominates
+pub fn solve_quadratic(a: f64, b: f64, c: f64) -> Roots {
+    let mut roots = Roots::new();
+
+    if a.abs() < 1e-12 {
+        if b.abs() > 1e-12 {
+            roots.push(-c / b);
+        }
+        return roots;
+    }
+
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return roots;
+    }
+
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    if q == 0.0 {
+        // b and c are both zero
+        roots.push(0.0);
+        roots.push(0.0);
+    } else {
+        roots.push(q / a);
+        roots.push(c / q);
+    }
+    roots.sorted()
+}
+
+// x^3 + a x^2 + b x + c = 0, by the trigonometric method when there are
+// three real roots and Cardano's formula when there's one
+pub fn solve_cubic(a: f64, b: f64, c: f64) -> Roots {
+    let mut roots = Roots::new();
+    let q = (a * a - 3.0 * b) / 9.0;
+    let r = (2.0 * a * a * a - 9.0 * a * b + 27.0 * c) / 54.0;
+    let shift = a / 3.0;
+
+    if r * r < q * q * q {
+        let theta = (r / (q * q * q).sqrt()).clamp(-1.0, 1.0).acos();
+        let m = -2.0 * q.sqrt();
+        roots.push(m * (theta / 3.0).cos() - shift);
+        roots.push(m * ((theta + 2.0 * PI) / 3.0).cos() - shift);
+        roots.push(m * ((theta - 2.0 * PI) / 3.0).cos() - shift);
+    } else {
+        let big_a = -r.signum() * (r.abs() + (r * r - q * q * q).sqrt()).cbrt();
+        let big_b = if big_a == 0.0 { 0.0 } else { q / big_a };
+        roots.push(big_a + big_b - shift);
+    }
+    roots.sorted()
+}
+
+// c[0] x^4 + c[1] x^3 + c[2] x^2 + c[3] x + c[4] = 0 with Ferrari's method.
+// The closed form loses precision easily, so each root is polished with a
+// couple of Newton steps on the original polynomial
+pub fn solve_quartic(c: [f64; 5]) -> Roots {
+    if c[0].abs() < 1e-12 {
+        let cubic = solve_cubic_general(c[1], c[2], c[3], c[4]);
+        return cubic;
+    }
+
+    let (a, b, cc, d) = (c[1] / c[0], c[2] / c[0], c[3] / c[0], c[4] / c[0]);
+
+    // Depressed quartic y^4 + p y^2 + q y + r for x = y - a / 4
+    let a2 = a * a;
+    let p = b - 3.0 * a2 / 8.0;
+    let q = cc - a * b / 2.0 + a2 * a / 8.0;
+    let r = d - a * cc / 4.0 + a2 * b / 16.0 - 3.0 * a2 * a2 / 256.0;
+    let shift = a / 4.0;
+
+    let mut roots = Roots::new();
+    if q.abs() < 1e-12 {
+        // Biquadratic, a quadratic in y^2
+        for z in solve_quadratic(1.0, p, r).as_slice() {
+            if *z >= 0.0 {
+                roots.push(z.sqrt() - shift);
+                roots.push(-z.sqrt() - shift);
+            }
+        }
+    } else {
+        // Any positive root m of the resolvent cubic splits the quartic
+        // into two quadratics
+        let m = solve_cubic(p, p * p / 4.0 - r, -q * q / 8.0)
+            .as_slice()
+            .iter()
+            .copied()
+            .fold(f64::NEG_INFINITY, f64::max);
+        if m <= 0.0 {
+            return roots;
+        }
+
+        let s = (2.0 * m).sqrt();
+        for (sign, offset) in [(1.0, -q / (2.0 * s)), (-1.0, q / (2.0 * s))] {
+            for y in solve_quadratic(1.0, sign * s, p / 2.0 + m + offset).as_slice() {
+                roots.push(y - shift);
+            }
+        }
+    }
+
+    for x in &mut roots.values[..roots.len] {
+        for _ in 0..2 {
+            let f = (((c[0] * *x + c[1]) * *x + c[2]) * *x + c[3]) * *x + c[4];
+            let df = ((4.0 * c[0] * *x + 3.0 * c[1]) * *x + 2.0 * c[2]) * *x + c[3];
+            if df.abs() > 1e-12 {
+                *x -= f / df;
+            }
+        }
+    }
+    roots.sorted()
+}
+
+// a x^3 + b x^2 + c x + d = 0 for any leading coefficient
+fn solve_cubic_general(a: f64, b: f64, c: f64, d: f64) -> Roots {
+    if a.abs() < 1e-12 {
+        solve_quadratic(b, c, d)
+    } else {
+        solve_cubic(b / a, c / a, d / a)
+    }
+}
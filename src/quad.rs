@@ -0,0 +1,276 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hittable::*;
+use crate::material::Material;
+use crate::math::rand_unit;
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::vec3::*;
+
+// Which part of the plane spanned by a quad's edges it covers, in terms of
+// the hit point's coordinates (a, b) along u and v
+#[derive(Clone, Copy)]
+pub enum QuadShape {
+    // 0 <= a, b <= 1
+    Parallelogram,
+    // a, b >= 0 and a + b <= 1
+    Triangle,
+    // a^2 + b^2 <= 1, with q at the center and u and v as radii
+    Disk,
+}
+
+// Flat primitive spanned by the edge vectors u and v from the point q
+pub struct Quad {
+    q: Point3,
+    u: Vec3,
+    v: Vec3,
+    shape: QuadShape,
+    mat: Arc<dyn Material>,
+    normal: Vec3,
+    // Plane equation, dot(normal, p) = d
+    d: f64,
+    // n / |n|^2 for n = u x v, which turns offsets in the plane into (a, b)
+    w: Vec3,
+    area: f64,
+}
+
+impl Quad {
+    pub fn new(q: Point3, u: Vec3, v: Vec3, mat: Arc<dyn Material>) -> Quad {
+        Quad::with_shape(q, u, v, QuadShape::Parallelogram, mat)
+    }
+
+    // Triangle with corners q, q + u and q + v
+    pub fn triangle(q: Point3, u: Vec3, v: Vec3, mat: Arc<dyn Material>) -> Quad {
+        Quad::with_shape(q, u, v, QuadShape::Triangle, mat)
+    }
+
+    // Disk facing along normal
+    pub fn disk(center: Point3, normal: Vec3, radius: f64, mat: Arc<dyn Material>) -> Quad {
+        let onb = Onb::from_w(&normal);
+        let (u, v) = (onb.u * radius, onb.v * radius);
+        // Keep u x v, which decides the front face, pointing along normal
+        if dot(&cross(&u, &v), &normal) < 0.0 {
+            Quad::with_shape(center, v, u, QuadShape::Disk, mat)
+        } else {
+            Quad::with_shape(center, u, v, QuadShape::Disk, mat)
+        }
+    }
+
+    // Axis aligned rectangles at z = k, y = k and x = k, facing +z, +y and
+    // +x respectively
+    pub fn xy_rect(x0: f64, x1: f64, y0: f64, y1: f64, k: f64, mat: Arc<dyn Material>) -> Quad {
+        Quad::new(
+            Point3::new(x0, y0, k),
+            Vec3::new(x1 - x0, 0.0, 0.0),
+            Vec3::new(0.0, y1 - y0, 0.0),
+            mat,
+        )
+    }
+
+    pub fn xz_rect(x0: f64, x1: f64, z0: f64, z1: f64, k: f64, mat: Arc<dyn Material>) -> Quad {
+        Quad::new(
+            Point3::new(x0, k, z0),
+            Vec3::new(0.0, 0.0, z1 - z0),
+            Vec3::new(x1 - x0, 0.0, 0.0),
+            mat,
+        )
+    }
+
+    pub fn yz_rect(y0: f64, y1: f64, z0: f64, z1: f64, k: f64, mat: Arc<dyn Material>) -> Quad {
+        Quad::new(
+            Point3::new(k, y0, z0),
+            Vec3::new(0.0, y1 - y0, 0.0),
+            Vec3::new(0.0, 0.0, z1 - z0),
+            mat,
+        )
+    }
+
+    pub fn with_shape(
+        q: Point3,
+        u: Vec3,
+        v: Vec3,
+        shape: QuadShape,
+        mat: Arc<dyn Material>,
+    ) -> Quad {
+        let n = cross(&u, &v);
+        let normal = normalized(n);
+        let area = match shape {
+            QuadShape::Parallelogram => n.length(),
+            QuadShape::Triangle => 0.5 * n.length(),
+            QuadShape::Disk => PI * n.length(),
+        };
+
+        Quad {
+            q,
+            u,
+            v,
+            shape,
+            mat,
+            normal,
+            d: dot(&normal, &q),
+            w: n / n.length_sq(),
+            area,
+        }
+    }
+
+    fn contains(&self, a: f64, b: f64) -> bool {
+        match self.shape {
+            QuadShape::Parallelogram => (0.0..=1.0).contains(&a) && (0.0..=1.0).contains(&b),
+            QuadShape::Triangle => a >= 0.0 && b >= 0.0 && a + b <= 1.0,
+            QuadShape::Disk => a * a + b * b <= 1.0,
+        }
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        // Ray is parallel to the plane
+        let denom = dot(&self.normal, &r.dir);
+        if denom.abs() < 1e-8 {
+            return false;
+        }
+
+        let t = (self.d - dot(&self.normal, &r.origin)) / denom;
+        if t < t_min || t > t_max {
+            return false;
+        }
+
+        // Coordinates of the hit point along the edges
+        let p = r.at(t);
+        let offset = p - self.q;
+        let a = dot(&self.w, &cross(&offset, &self.v));
+        let b = dot(&self.w, &cross(&self.u, &offset));
+        if !self.contains(a, b) {
+            return false;
+        }
+
+        *rec = HitRecord::new(p, t, r, &self.normal, self.mat.clone());
+        (rec.u, rec.v) = match self.shape {
+            QuadShape::Disk => (0.5 * (a + 1.0), 0.5 * (b + 1.0)),
+            _ => (a, b),
+        };
+
+        true
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        let corners = match self.shape {
+            QuadShape::Parallelogram => [
+                self.q,
+                self.q + self.u,
+                self.q + self.v,
+                self.q + self.u + self.v,
+            ],
+            QuadShape::Triangle => [self.q, self.q + self.u, self.q + self.v, self.q],
+            // The disk fits in the parallelogram of its radii around q
+            QuadShape::Disk => [
+                self.q - self.u - self.v,
+                self.q + self.u - self.v,
+                self.q - self.u + self.v,
+                self.q + self.u + self.v,
+            ],
+        };
+
+        *output_box = corners
+            .iter()
+            .fold(Aabb::empty(), |bbox, c| bbox.expand(*c))
+            .padded();
+        true
+    }
+
+    // Uniform over the area, converted to solid angle
+    fn pdf_value(&self, origin: &Point3, v: &Vec3) -> f64 {
+        let mut rec = HitRecord::blank();
+        if !self.hit(&Ray::new(*origin, *v, 0.0), 0.001, f64::INFINITY, &mut rec) {
+            return 0.0;
+        }
+
+        let dist_sq = rec.t * rec.t * v.length_sq();
+        let cosine = (dot(v, &self.normal) / v.length()).abs();
+        if cosine < 1e-8 {
+            return 0.0;
+        }
+
+        dist_sq / (cosine * self.area)
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        let (a, b) = match self.shape {
+            QuadShape::Parallelogram => (rand_unit(), rand_unit()),
+            QuadShape::Triangle => {
+                // Folding the unit square in half gives uniform points
+                let (a, b) = (rand_unit(), rand_unit());
+                if a + b > 1.0 {
+                    (1.0 - a, 1.0 - b)
+                } else {
+                    (a, b)
+                }
+            }
+            QuadShape::Disk => {
+                let p = Vec3::rand_in_disk();
+                (p.x, p.y)
+            }
+        };
+
+        self.q + self.u * a + self.v * b - *origin
+    }
+}
+
+// Axis aligned box made of six outward facing quads, spanning the two
+// opposite corners a and b
+pub struct Cuboid {
+    sides: HittableList,
+    bbox: Aabb,
+}
+
+impl Cuboid {
+    pub fn new(a: Point3, b: Point3, mat: Arc<dyn Material>) -> Cuboid {
+        let min = Point3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z));
+        let max = Point3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z));
+
+        let dx = Vec3::new(max.x - min.x, 0.0, 0.0);
+        let dy = Vec3::new(0.0, max.y - min.y, 0.0);
+        let dz = Vec3::new(0.0, 0.0, max.z - min.z);
+
+        // Edges ordered so u x v points out of the box, with uvs running
+        // the same way as the faces' images when seen from outside
+        let mut sides = HittableList::new();
+        let faces = [
+            (Point3::new(min.x, min.y, max.z), dx, dy),  // front
+            (Point3::new(max.x, min.y, max.z), -dz, dy), // right
+            (Point3::new(max.x, min.y, min.z), -dx, dy), // back
+            (Point3::new(min.x, min.y, min.z), dz, dy),  // left
+            (Point3::new(min.x, max.y, max.z), dx, -dz), // top
+            (Point3::new(min.x, min.y, min.z), dx, dz),  // bottom
+        ];
+        for (q, u, v) in faces {
+            sides.add(Arc::new(Quad::new(q, u, v, mat.clone())));
+        }
+
+        Cuboid {
+            sides,
+            bbox: Aabb::new(min, max).padded(),
+        }
+    }
+}
+
+impl Hittable for Cuboid {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        self.bbox.hit(r, t_min, t_max) && self.sides.hit(r, t_min, t_max, rec)
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        *output_box = self.bbox;
+        true
+    }
+
+    fn pdf_value(&self, origin: &Point3, v: &Vec3) -> f64 {
+        self.sides.pdf_value(origin, v)
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        self.sides.random(origin)
+    }
+}
@@ -0,0 +1,346 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hittable::*;
+use crate::material::Material;
+use crate::poly::{solve_quadratic, Roots};
+use crate::ray::Ray;
+use crate::vec3::*;
+
+// Quadric surfaces of revolution around the y axis, built in a canonical
+// position and placed in the scene with a transform. Each wraps u once
+// around the axis and runs v along it
+
+// Where a ray meets one part of a shape, before picking the closest part
+struct SurfaceHit {
+    t: f64,
+    normal: Vec3,
+    u: f64,
+    v: f64,
+}
+
+// Angle around the y axis as a u coordinate in [0, 1], matching spheres
+pub fn azimuth_u(p: &Point3) -> f64 {
+    ((-p.z).atan2(p.x) + PI) / (2.0 * PI)
+}
+
+// First root in range whose point lies between y_min and y_max
+fn side_hit(roots: Roots, r: &Ray, t_min: f64, t_max: f64, y_min: f64, y_max: f64) -> Option<f64> {
+    roots.as_slice().iter().copied().find(|&t| {
+        let y = r.origin.y + t * r.dir.y;
+        t >= t_min && t <= t_max && y >= y_min && y <= y_max
+    })
+}
+
+// Disk of the given radius closing the shape at height y, facing up or down
+fn cap_hit(r: &Ray, y: f64, radius: f64, up: bool, t_min: f64, t_max: f64) -> Option<SurfaceHit> {
+    if r.dir.y.abs() < 1e-12 {
+        return None;
+    }
+    let t = (y - r.origin.y) / r.dir.y;
+    if t < t_min || t > t_max {
+        return None;
+    }
+
+    let p = r.at(t);
+    if p.x * p.x + p.z * p.z > radius * radius {
+        return None;
+    }
+
+    Some(SurfaceHit {
+        t,
+        normal: Vec3::new(0.0, if up { 1.0 } else { -1.0 }, 0.0),
+        u: 0.5 * (p.x / radius + 1.0),
+        v: 0.5 * (p.z / radius + 1.0),
+    })
+}
+
+// Fill in rec from the closest of the parts that were hit
+fn record(
+    r: &Ray,
+    hits: impl IntoIterator<Item = Option<SurfaceHit>>,
+    mat: &Arc<dyn Material>,
+    rec: &mut HitRecord,
+) -> bool {
+    let closest = hits
+        .into_iter()
+        .flatten()
+        .min_by(|a, b| a.t.total_cmp(&b.t));
+
+    match closest {
+        Some(hit) => {
+            *rec = HitRecord::new(r.at(hit.t), hit.t, r, &hit.normal, mat.clone());
+            (rec.u, rec.v) = (hit.u, hit.v);
+            true
+        }
+        None => false,
+    }
+}
+
+// Solid of revolution from y_min to y_max with the given radius at each
+// end, as a bounding box
+fn revolution_box(radius: f64, y_min: f64, y_max: f64) -> Aabb {
+    Aabb::new(
+        Point3::new(-radius, y_min, -radius),
+        Point3::new(radius, y_max, radius),
+    )
+    .padded()
+}
+
+// Cylinder of the given radius around the y axis from y_min to y_max,
+// optionally closed with disks at both ends
+pub struct Cylinder {
+    radius: f64,
+    y_min: f64,
+    y_max: f64,
+    capped: bool,
+    mat: Arc<dyn Material>,
+}
+
+impl Cylinder {
+    pub fn new(
+        radius: f64,
+        y_min: f64,
+        y_max: f64,
+        capped: bool,
+        mat: Arc<dyn Material>,
+    ) -> Cylinder {
+        Cylinder {
+            radius,
+            y_min: y_min.min(y_max),
+            y_max: y_min.max(y_max),
+            capped,
+            mat,
+        }
+    }
+}
+
+impl Hittable for Cylinder {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        // x^2 + z^2 = radius^2
+        let (o, d) = (r.origin, r.dir);
+        let roots = solve_quadratic(
+            d.x * d.x + d.z * d.z,
+            2.0 * (o.x * d.x + o.z * d.z),
+            o.x * o.x + o.z * o.z - self.radius * self.radius,
+        );
+
+        let side = side_hit(roots, r, t_min, t_max, self.y_min, self.y_max).map(|t| {
+            let p = r.at(t);
+            SurfaceHit {
+                t,
+                normal: Vec3::new(p.x, 0.0, p.z) / self.radius,
+                u: azimuth_u(&p),
+                v: (p.y - self.y_min) / (self.y_max - self.y_min),
+            }
+        });
+
+        let (bottom, top) = if self.capped {
+            (
+                cap_hit(r, self.y_min, self.radius, false, t_min, t_max),
+                cap_hit(r, self.y_max, self.radius, true, t_min, t_max),
+            )
+        } else {
+            (None, None)
+        };
+
+        record(r, [side, bottom, top], &self.mat, rec)
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        *output_box = revolution_box(self.radius, self.y_min, self.y_max);
+        true
+    }
+}
+
+// Cone with its base of the given radius at y = 0 and its apex at y =
+// height, optionally closed with a disk at the base
+pub struct Cone {
+    radius: f64,
+    height: f64,
+    capped: bool,
+    mat: Arc<dyn Material>,
+}
+
+impl Cone {
+    pub fn new(radius: f64, height: f64, capped: bool, mat: Arc<dyn Material>) -> Cone {
+        Cone {
+            radius,
+            height,
+            capped,
+            mat,
+        }
+    }
+}
+
+impl Hittable for Cone {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        // x^2 + z^2 = k^2 (height - y)^2 with k the radius per unit height
+        let (o, d) = (r.origin, r.dir);
+        let k_sq = (self.radius / self.height).powi(2);
+        let h = self.height - o.y;
+        let roots = solve_quadratic(
+            d.x * d.x + d.z * d.z - k_sq * d.y * d.y,
+            2.0 * (o.x * d.x + o.z * d.z + k_sq * h * d.y),
+            o.x * o.x + o.z * o.z - k_sq * h * h,
+        );
+
+        let side = side_hit(roots, r, t_min, t_max, 0.0, self.height).map(|t| {
+            let p = r.at(t);
+            // Gradient of the implicit surface, which vanishes at the apex
+            let gradient = Vec3::new(p.x, k_sq * (self.height - p.y), p.z);
+            let normal = if gradient.length_sq() > 1e-16 {
+                normalized(gradient)
+            } else {
+                Vec3::new(0.0, 1.0, 0.0)
+            };
+            SurfaceHit {
+                t,
+                normal,
+                u: azimuth_u(&p),
+                v: p.y / self.height,
+            }
+        });
+
+        let base = if self.capped {
+            cap_hit(r, 0.0, self.radius, false, t_min, t_max)
+        } else {
+            None
+        };
+
+        record(r, [side, base], &self.mat, rec)
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        *output_box = revolution_box(self.radius, 0.0, self.height);
+        true
+    }
+}
+
+// Bowl y = height (x^2 + z^2) / radius^2 with its tip at the origin,
+// reaching the given radius at y = height, optionally closed with a lid
+pub struct Paraboloid {
+    radius: f64,
+    height: f64,
+    capped: bool,
+    mat: Arc<dyn Material>,
+}
+
+impl Paraboloid {
+    pub fn new(radius: f64, height: f64, capped: bool, mat: Arc<dyn Material>) -> Paraboloid {
+        Paraboloid {
+            radius,
+            height,
+            capped,
+            mat,
+        }
+    }
+}
+
+impl Hittable for Paraboloid {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        // x^2 + z^2 = k y with k = radius^2 / height
+        let (o, d) = (r.origin, r.dir);
+        let k = self.radius * self.radius / self.height;
+        let roots = solve_quadratic(
+            d.x * d.x + d.z * d.z,
+            2.0 * (o.x * d.x + o.z * d.z) - k * d.y,
+            o.x * o.x + o.z * o.z - k * o.y,
+        );
+
+        let side = side_hit(roots, r, t_min, t_max, 0.0, self.height).map(|t| {
+            let p = r.at(t);
+            SurfaceHit {
+                t,
+                normal: normalized(Vec3::new(2.0 * p.x, -k, 2.0 * p.z)),
+                u: azimuth_u(&p),
+                v: p.y / self.height,
+            }
+        });
+
+        let lid = if self.capped {
+            cap_hit(r, self.height, self.radius, true, t_min, t_max)
+        } else {
+            None
+        };
+
+        record(r, [side, lid], &self.mat, rec)
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        *output_box = revolution_box(self.radius, 0.0, self.height);
+        true
+    }
+}
+
+// Hyperboloid of one sheet from y = -half_height to half_height, with the
+// waist radius at y = 0 and radius at both ends, optionally closed with
+// disks. An end radius smaller than the waist bulges out like a barrel
+pub struct Hyperboloid {
+    waist: f64,
+    radius: f64,
+    half_height: f64,
+    capped: bool,
+    mat: Arc<dyn Material>,
+}
+
+impl Hyperboloid {
+    pub fn new(
+        waist: f64,
+        radius: f64,
+        half_height: f64,
+        capped: bool,
+        mat: Arc<dyn Material>,
+    ) -> Hyperboloid {
+        Hyperboloid {
+            waist,
+            radius,
+            half_height,
+            capped,
+            mat,
+        }
+    }
+}
+
+impl Hittable for Hyperboloid {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        // x^2 + z^2 - s y^2 = waist^2, with s chosen to reach radius at the
+        // ends
+        let (o, d) = (r.origin, r.dir);
+        let s = (self.radius * self.radius - self.waist * self.waist)
+            / (self.half_height * self.half_height);
+        let roots = solve_quadratic(
+            d.x * d.x + d.z * d.z - s * d.y * d.y,
+            2.0 * (o.x * d.x + o.z * d.z - s * o.y * d.y),
+            o.x * o.x + o.z * o.z - s * o.y * o.y - self.waist * self.waist,
+        );
+
+        let side = side_hit(roots, r, t_min, t_max, -self.half_height, self.half_height).map(|t| {
+            let p = r.at(t);
+            SurfaceHit {
+                t,
+                normal: normalized(Vec3::new(p.x, -s * p.y, p.z)),
+                u: azimuth_u(&p),
+                v: 0.5 * (p.y / self.half_height + 1.0),
+            }
+        });
+
+        let (bottom, top) = if self.capped {
+            (
+                cap_hit(r, -self.half_height, self.radius, false, t_min, t_max),
+                cap_hit(r, self.half_height, self.radius, true, t_min, t_max),
+            )
+        } else {
+            (None, None)
+        };
+
+        record(r, [side, bottom, top], &self.mat, rec)
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        let widest = self.waist.max(self.radius);
+        *output_box = revolution_box(widest, -self.half_height, self.half_height);
+        true
+    }
+}
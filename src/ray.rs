@@ -3,6 +3,8 @@ use crate::vec3::{Point3, Vec3};
 pub struct Ray {
     pub origin: Point3,
     pub dir: Vec3,
+    // When the ray was fired, for objects that move while the shutter is open
+    pub time: f64,
 }
 
 impl Ray {
@@ -10,14 +12,15 @@ impl Ray {
         self.origin + self.dir * t
     }
 
-    pub fn new(origin: Point3, dir: Vec3) -> Ray {
-        Ray { origin, dir }
+    pub fn new(origin: Point3, dir: Vec3, time: f64) -> Ray {
+        Ray { origin, dir, time }
     }
 
     pub fn blank() -> Ray {
         Ray {
             origin: Point3::new(0.0, 0.0, 0.0),
             dir: Vec3::new(0.0, 0.0, 0.0),
+            time: 0.0,
         }
     }
 }
@@ -0,0 +1,2055 @@
+// Scenes are described in TOML files. An example:
+//
+//     [image]
+//     width = 400
+//     aspect_ratio = 1.7778   # or height = 225
+//     samples = 100
+//     bounce_depth = 50
+//     roulette_depth = 5       # optional, bounces before paths may end early
+//
+//     [camera]
+//     position = [7.5, 1.0, 5.0]
+//     look_at = [0.0, 0.0, -1.0]
+//     up = [0.0, 1.0, 0.0]     # optional
+//     vfov = 30.0
+//     aperture = 0.1           # optional, defaults to a pinhole
+//     focus_dist = 10.0        # optional, defaults to the look_at distance
+//     shutter = [0.0, 1.0]     # optional, open and close times for motion blur
+//
+//     [background]             # optional, defaults to the white to blue sky
+//     type = "gradient"
+//     bottom = [1.0, 1.0, 1.0]
+//     top = [0.5, 0.7, 1.0]
+//
+//     [textures.checks]
+//     type = "checker"
+//     even = [0.2, 0.3, 0.1]
+//     odd = [0.9, 0.9, 0.9]
+//     scale = 0.5
+//
+//     [materials.ground]
+//     type = "lambertian"
+//     albedo = "checks"         # or a color like [0.8, 0.8, 0.6]
+//
+//     [[objects]]
+//     type = "plane"
+//     point = [0.0, 0.0, 0.0]
+//     normal = [0.0, 1.0, 0.0]
+//     material = "ground"
+//
+// Background types are solid (color), gradient (bottom, top) and
+// environment (file, an equirectangular .hdr path relative to the scene
+// file, with optional rotation in degrees about the y axis and intensity).
+// Environments are sampled as lights, favoring their brightest parts.
+//
+// Texture types are checker (even, odd, with cubes of side scale, or
+// squares of uv when uv = true, best for planes), image (file, a PNG or
+// JPEG relative to the scene file, wrapped over the object's uv
+// coordinates) and the procedural noise, marble, wood and
+// worley. Procedural textures blend from low to high (black to white by
+// default) and take an optional seed and scale, the feature frequency.
+// noise has a pattern (plain, fbm or turbulence), and it, marble and wood
+// take fractal octaves, lacunarity and gain, with marble and wood also
+// taking a turbulence strength. worley has a feature (f1, f2 or
+// f2_minus_f1).
+//
+// Material types are lambertian (albedo), metal (albedo, fuzz), dielectric
+// (ior), diffuse_light (emit, optionally scaled by intensity), and for
+// volumes isotropic (albedo) and henyey_greenstein (albedo, g, from -1 for
+// back scattering to 1 for forward), where albedo is a color or a texture
+// name.
+//
+// Object types are sphere (center, radius), triangle (vertices), plane
+// (point, normal, with uvs repeating every uv_scale units), quad (corner
+// and edges u and v), disk (center, normal, radius), box (min and max
+// corners), cylinder, cone and paraboloid (radius, height), hyperboloid
+// (waist, radius, height), torus (major_radius, minor_radius), bezier
+// (file, bicubic patches in the Utah teapot's format described in
+// bezier.rs) and mesh (file, an OBJ path), with files relative to the
+// scene file, each naming one of the materials. Cylinders, cones and
+// paraboloids stand on y = 0 around the y axis while hyperboloids and tori
+// are centered on the origin, ready to be placed with a transform. All but
+// tori are closed with flat caps unless capped = false. Meshes use their
+// own MTL materials where they have them and the named material everywhere
+// else. Spheres, triangles, quads, disks, boxes and meshes with a
+// diffuse_light material are also sampled directly as light sources,
+// unless they move.
+//
+// A heightfield (file) is terrain from a grayscale PNG or JPEG, black at
+// height 0 and white at 1, or from a raw grid of heights in the format
+// described in heightfield.rs, relative to the scene file. The samples
+// spread over a rectangle centered on the origin in the xz plane with its
+// longer side 1 unit long, and are sized with a transform such as
+// transform = [{ scale = [20.0, 3.0, 20.0] }]. Its uvs cover it once, with
+// the image's top edge at -z.
+//
+// An sdf (shape) is the surface of a signed distance field, a tree of
+// shapes centered on the origin, sphere (radius), box (size), torus
+// (major_radius, minor_radius) and capsule (a, b, radius), combined with
+// smooth_union (a, b, blending within k), subtraction (b cut from a),
+// repeat (shape, copied every period along each axis, 0 for none), twist
+// (shape, rate in degrees per unit of height about the y axis, of a shape
+// that doesn't repeat) and translate (shape, offset), e.g.
+// shape = { type = "smooth_union", k = 0.2, a = { type = "sphere",
+// radius = 1.0 }, b = { type = "translate", offset = [1, 0, 0],
+// shape = { type = "sphere", radius = 0.5 } } }.
+//
+// A medium (boundary, density) fills a boundary object with fog, using
+// the boundary's material, isotropic or henyey_greenstein, to scatter
+// light inside it. The boundary can be any object a csg can combine other
+// than a plane or a csg, e.g. boundary = { type = "sphere",
+// center = [0, 1, 0], radius = 1.0, material = "smoke" }. A volume (file,
+// density) varies its density over a voxel grid file in the format
+// described in volume.rs, filling a box around the origin with its longest
+// side 1 unit long, and scatters with its material. density scales the
+// grid's values.
+//
+// A csg (operation, objects) combines closed objects, each with its own
+// material and placement, into their union, intersection or difference,
+// where a difference takes every later object away from the first. The
+// objects can be spheres, boxes, tori, sdfs, capped cylinders, cones,
+// paraboloids and hyperboloids, meshes with every edge shared by two
+// faces, other csgs and planes, which count as the half space behind them
+// for cutting objects flat. The objects can be listed inline or as
+// [[objects.objects]] tables after the csg's own.
+//
+// Any object can take a transform, a list of steps applied in order such as
+// transform = [{ scale = 2.0 }, { rotate_y = 45.0 }, { translate = [1, 0, 0] }].
+// Steps are translate, scale (a number or per axis), rotate_x, rotate_y,
+// rotate_z and rotate = { axis, angle } with angles in degrees, and matrix
+// (4 rows of an affine matrix). Meshes used by several objects are only
+// loaded once, with every transformed copy sharing the same geometry.
+//
+// Objects only move while the camera's shutter is open, which blurs them
+// along their path. A sphere can take a center_end it moves to in a
+// straight line, over motion_times = [start, end] (0 to 1 by default). Any
+// object can instead of a transform take keyframes, each a time and a
+// transform with the same kinds of steps in the same order, such as
+// keyframes = [{ time = 0.0, transform = [{ rotate_y = 0.0 }] },
+//              { time = 1.0, transform = [{ rotate_y = 90.0 }] }].
+// Step parameters are blended between keyframes, and objects hold still
+// before the first keyframe and after the last.
+
+use std::cell::{OnceCell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use crate::background::*;
+use crate::bezier::{load_patches, BezierPatch};
+use crate::bvh::BvhNode;
+use crate::camera::Camera;
+use crate::csg::{Csg, CsgOp};
+use crate::heightfield::{HeightMap, Heightfield};
+use crate::hittable::{Hittable, HittableList};
+use crate::mat4::Mat4;
+use crate::material::*;
+use crate::math::{deg_to_rad, rand_unit};
+use crate::medium::ConstantMedium;
+use crate::mesh::{is_closed, TriangleMesh};
+use crate::noise::{Perlin, Worley};
+use crate::obj::load_obj_meshes;
+use crate::plane::Plane;
+use crate::quad::{Cuboid, Quad};
+use crate::quadric::{Cone, Cylinder, Hyperboloid, Paraboloid};
+use crate::sdf::{Sdf, SdfObject};
+use crate::sphere::Sphere;
+use crate::texture::*;
+use crate::torus::Torus;
+use crate::transform::{compose, Animated, Keyframe, TransformStep, Transformed};
+use crate::triangle::Triangle;
+use crate::vec3::*;
+use crate::volume::{GridMedium, VoxelGrid};
+
+#[derive(Clone, Copy)]
+pub struct Image {
+    pub height: u32,
+    pub width: u32,
+    pub samples: u32,
+    // Hard limit on bounces per path
+    pub bounce_depth: u32,
+    // Bounces after which paths are randomly terminated by Russian roulette
+    pub roulette_depth: u32,
+}
+
+// Camera placement, kept separate from the Camera itself so the image size
+// can still be changed after a scene is loaded
+#[derive(Clone, Copy)]
+pub struct CameraSettings {
+    pub position: Point3,
+    pub look_at: Point3,
+    pub up: Vec3,
+    pub vfov: f64,
+    pub aperture: f64,
+    pub focus_dist: f64,
+    pub shutter_open: f64,
+    pub shutter_close: f64,
+}
+
+impl CameraSettings {
+    pub fn build(&self, aspect_ratio: f64) -> Camera {
+        Camera::new(
+            self.position,
+            self.look_at,
+            self.up,
+            self.vfov,
+            aspect_ratio,
+            self.aperture,
+            self.focus_dist,
+        )
+        .with_shutter(self.shutter_open, self.shutter_close)
+    }
+}
+
+pub struct Scene {
+    pub image: Image,
+    pub camera: CameraSettings,
+    pub world: HittableList,
+    // Emissive objects, also in world, that get sampled directly
+    pub lights: HittableList,
+    pub background: Arc<dyn Background>,
+}
+
+#[derive(Debug)]
+pub struct SceneError {
+    pub path: PathBuf,
+    // Which part of the file is at fault, e.g. "objects[2] (sphere)"
+    pub entry: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.entry {
+            Some(entry) => write!(f, "{}: {}: {}", self.path.display(), entry, self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDesc {
+    image: ImageDesc,
+    camera: CameraDesc,
+    background: Option<BackgroundDesc>,
+    #[serde(default)]
+    textures: BTreeMap<String, TextureDesc>,
+    #[serde(default)]
+    materials: BTreeMap<String, MaterialDesc>,
+    #[serde(default)]
+    objects: Vec<ObjectDesc>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ImageDesc {
+    width: u32,
+    height: Option<u32>,
+    aspect_ratio: Option<f64>,
+    #[serde(default = "default_samples")]
+    samples: u32,
+    #[serde(default = "default_bounce_depth")]
+    bounce_depth: u32,
+    #[serde(default = "default_roulette_depth")]
+    roulette_depth: u32,
+}
+
+fn default_samples() -> u32 {
+    100
+}
+
+fn default_bounce_depth() -> u32 {
+    50
+}
+
+fn default_roulette_depth() -> u32 {
+    5
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDesc {
+    position: [f64; 3],
+    look_at: [f64; 3],
+    #[serde(default = "default_up")]
+    up: [f64; 3],
+    vfov: f64,
+    #[serde(default)]
+    aperture: f64,
+    focus_dist: Option<f64>,
+    #[serde(default)]
+    shutter: [f64; 2],
+}
+
+fn default_up() -> [f64; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum BackgroundDesc {
+    Solid {
+        color: [f64; 3],
+    },
+    Gradient {
+        bottom: [f64; 3],
+        top: [f64; 3],
+    },
+    Environment {
+        file: String,
+        #[serde(default)]
+        rotation: f64,
+        #[serde(default = "default_intensity")]
+        intensity: f64,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureDesc {
+    Checker {
+        even: [f64; 3],
+        odd: [f64; 3],
+        #[serde(default = "default_checker_scale")]
+        scale: f64,
+        // Checks over uv coordinates rather than in space
+        #[serde(default)]
+        uv: bool,
+    },
+    Image {
+        file: String,
+    },
+    Noise {
+        #[serde(default)]
+        seed: u64,
+        #[serde(default = "default_noise_scale")]
+        scale: f64,
+        #[serde(default = "default_noise_pattern")]
+        pattern: String,
+        #[serde(default = "default_octaves")]
+        octaves: u32,
+        #[serde(default = "default_lacunarity")]
+        lacunarity: f64,
+        #[serde(default = "default_gain")]
+        gain: f64,
+        #[serde(default = "default_low")]
+        low: [f64; 3],
+        #[serde(default = "default_high")]
+        high: [f64; 3],
+    },
+    Marble {
+        #[serde(default)]
+        seed: u64,
+        #[serde(default = "default_noise_scale")]
+        scale: f64,
+        #[serde(default = "default_octaves")]
+        octaves: u32,
+        #[serde(default = "default_lacunarity")]
+        lacunarity: f64,
+        #[serde(default = "default_gain")]
+        gain: f64,
+        #[serde(default = "default_marble_turbulence")]
+        turbulence: f64,
+        #[serde(default = "default_low")]
+        low: [f64; 3],
+        #[serde(default = "default_high")]
+        high: [f64; 3],
+    },
+    Wood {
+        #[serde(default)]
+        seed: u64,
+        #[serde(default = "default_noise_scale")]
+        scale: f64,
+        #[serde(default = "default_octaves")]
+        octaves: u32,
+        #[serde(default = "default_lacunarity")]
+        lacunarity: f64,
+        #[serde(default = "default_gain")]
+        gain: f64,
+        #[serde(default = "default_wood_turbulence")]
+        turbulence: f64,
+        #[serde(default = "default_low")]
+        low: [f64; 3],
+        #[serde(default = "default_high")]
+        high: [f64; 3],
+    },
+    Worley {
+        #[serde(default)]
+        seed: u64,
+        #[serde(default = "default_noise_scale")]
+        scale: f64,
+        #[serde(default = "default_worley_feature")]
+        feature: String,
+        #[serde(default = "default_low")]
+        low: [f64; 3],
+        #[serde(default = "default_high")]
+        high: [f64; 3],
+    },
+}
+
+fn default_checker_scale() -> f64 {
+    1.0
+}
+
+fn default_noise_scale() -> f64 {
+    1.0
+}
+
+fn default_noise_pattern() -> String {
+    "turbulence".to_string()
+}
+
+fn default_octaves() -> u32 {
+    7
+}
+
+fn default_lacunarity() -> f64 {
+    2.0
+}
+
+fn default_gain() -> f64 {
+    0.5
+}
+
+fn default_marble_turbulence() -> f64 {
+    10.0
+}
+
+fn default_wood_turbulence() -> f64 {
+    0.5
+}
+
+fn default_worley_feature() -> String {
+    "f1".to_string()
+}
+
+fn default_low() -> [f64; 3] {
+    [0.0, 0.0, 0.0]
+}
+
+fn default_high() -> [f64; 3] {
+    [1.0, 1.0, 1.0]
+}
+
+// Material colors are either given directly or name a texture
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AlbedoDesc {
+    Color([f64; 3]),
+    Texture(String),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDesc {
+    Lambertian {
+        albedo: AlbedoDesc,
+    },
+    Metal {
+        albedo: AlbedoDesc,
+        #[serde(default)]
+        fuzz: f64,
+    },
+    Dielectric {
+        ior: f64,
+    },
+    DiffuseLight {
+        emit: [f64; 3],
+        #[serde(default = "default_intensity")]
+        intensity: f64,
+    },
+    Isotropic {
+        albedo: AlbedoDesc,
+    },
+    HenyeyGreenstein {
+        albedo: AlbedoDesc,
+        #[serde(default)]
+        g: f64,
+    },
+}
+
+fn default_intensity() -> f64 {
+    1.0
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ObjectDesc {
+    Sphere {
+        center: [f64; 3],
+        radius: f64,
+        material: String,
+        // Where the center moves to, for motion blur
+        center_end: Option<[f64; 3]>,
+        #[serde(default = "default_motion_times")]
+        motion_times: [f64; 2],
+        #[serde(default)]
+        transform: Vec<TransformDesc>,
+        #[serde(default)]
+        keyframes: Vec<KeyframeDesc>,
+    },
+    Triangle {
+        vertices: [[f64; 3]; 3],
+        material: String,
+        #[serde(default)]
+        transform: Vec<TransformDesc>,
+        #[serde(default)]
+        keyframes: Vec<KeyframeDesc>,
+    },
+    Plane {
+        point: [f64; 3],
+        normal: [f64; 3],
+        material: String,
+        #[serde(default = "default_uv_scale")]
+        uv_scale: f64,
+        #[serde(default)]
+        transform: Vec<TransformDesc>,
+        #[serde(default)]
+        keyframes: Vec<KeyframeDesc>,
+    },
+    // Parallelogram with a corner and two edges
+    Quad {
+        corner: [f64; 3],
+        u: [f64; 3],
+        v: [f64; 3],
+        material: String,
+        #[serde(default)]
+        transform: Vec<TransformDesc>,
+        #[serde(default)]
+        keyframes: Vec<KeyframeDesc>,
+    },
+    Disk {
+        center: [f64; 3],
+        normal: [f64; 3],
+        radius: f64,
+        material: String,
+        #[serde(default)]
+        transform: Vec<TransformDesc>,
+        #[serde(default)]
+        keyframes: Vec<KeyframeDesc>,
+    },
+    // Axis aligned, between two opposite corners
+    Box {
+        min: [f64; 3],
+        max: [f64; 3],
+        material: String,
+        #[serde(default)]
+        transform: Vec<TransformDesc>,
+        #[serde(default)]
+        keyframes: Vec<KeyframeDesc>,
+    },
+    // Shapes of revolution around the y axis, starting from y = 0 except
+    // for hyperboloids and tori, which are centered on the origin
+    Cylinder {
+        radius: f64,
+        height: f64,
+        material: String,
+        #[serde(default = "default_capped")]
+        capped: bool,
+        #[serde(default)]
+        transform: Vec<TransformDesc>,
+        #[serde(default)]
+        keyframes: Vec<KeyframeDesc>,
+    },
+    Cone {
+        radius: f64,
+        height: f64,
+        material: String,
+        #[serde(default = "default_capped")]
+        capped: bool,
+        #[serde(default)]
+        transform: Vec<TransformDesc>,
+        #[serde(default)]
+        keyframes: Vec<KeyframeDesc>,
+    },
+    Paraboloid {
+        radius: f64,
+        height: f64,
+        material: String,
+        #[serde(default = "default_capped")]
+        capped: bool,
+        #[serde(default)]
+        transform: Vec<TransformDesc>,
+        #[serde(default)]
+        keyframes: Vec<KeyframeDesc>,
+    },
+    Hyperboloid {
+        waist: f64,
+        radius: f64,
+        height: f64,
+        material: String,
+        #[serde(default = "default_capped")]
+        capped: bool,
+        #[serde(default)]
+        transform: Vec<TransformDesc>,
+        #[serde(default)]
+        keyframes: Vec<KeyframeDesc>,
+    },
+    Torus {
+        major_radius: f64,
+        minor_radius: f64,
+        material: String,
+        #[serde(default)]
+        transform: Vec<TransformDesc>,
+        #[serde(default)]
+        keyframes: Vec<KeyframeDesc>,
+    },
+    // Surface of a signed distance field, found by sphere tracing
+    Sdf {
+        shape: SdfDesc,
+        material: String,
+        #[serde(default)]
+        transform: Vec<TransformDesc>,
+        #[serde(default)]
+        keyframes: Vec<KeyframeDesc>,
+    },
+    // Terrain from a grayscale image or raw height grid
+    Heightfield {
+        file: String,
+        material: String,
+        #[serde(default)]
+        transform: Vec<TransformDesc>,
+        #[serde(default)]
+        keyframes: Vec<KeyframeDesc>,
+    },
+    // Bicubic Bézier patches from a file in the Utah teapot's format
+    Bezier {
+        file: String,
+        material: String,
+        #[serde(default)]
+        transform: Vec<TransformDesc>,
+        #[serde(default)]
+        keyframes: Vec<KeyframeDesc>,
+    },
+    Mesh {
+        file: String,
+        material: String,
+        #[serde(default)]
+        transform: Vec<TransformDesc>,
+        #[serde(default)]
+        keyframes: Vec<KeyframeDesc>,
+    },
+    // Density from a voxel grid file, with the material as the phase
+    // function
+    Volume {
+        file: String,
+        material: String,
+        #[serde(default = "default_density")]
+        density: f64,
+        #[serde(default)]
+        transform: Vec<TransformDesc>,
+        #[serde(default)]
+        keyframes: Vec<KeyframeDesc>,
+    },
+    // Boolean combination of closed objects, each placed as usual
+    Csg {
+        operation: CsgOpDesc,
+        objects: Vec<ObjectDesc>,
+        #[serde(default)]
+        transform: Vec<TransformDesc>,
+        #[serde(default)]
+        keyframes: Vec<KeyframeDesc>,
+    },
+    // Fog inside another object, which is placed as usual and whose
+    // material is the phase function
+    Medium {
+        boundary: Box<ObjectDesc>,
+        density: f64,
+    },
+}
+
+// One node of a distance field's tree, either a shape or a combinator of
+// the shapes under it
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum SdfDesc {
+    Sphere {
+        radius: f64,
+    },
+    Box {
+        size: [f64; 3],
+    },
+    Torus {
+        major_radius: f64,
+        minor_radius: f64,
+    },
+    Capsule {
+        a: [f64; 3],
+        b: [f64; 3],
+        radius: f64,
+    },
+    SmoothUnion {
+        a: Box<SdfDesc>,
+        b: Box<SdfDesc>,
+        k: f64,
+    },
+    Subtraction {
+        a: Box<SdfDesc>,
+        b: Box<SdfDesc>,
+    },
+    Repeat {
+        shape: Box<SdfDesc>,
+        period: [f64; 3],
+    },
+    // Degrees per unit of height
+    Twist {
+        shape: Box<SdfDesc>,
+        rate: f64,
+    },
+    Translate {
+        shape: Box<SdfDesc>,
+        offset: [f64; 3],
+    },
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum CsgOpDesc {
+    Union,
+    Intersection,
+    Difference,
+}
+
+fn default_uv_scale() -> f64 {
+    1.0
+}
+
+fn default_capped() -> bool {
+    true
+}
+
+fn default_density() -> f64 {
+    1.0
+}
+
+fn default_motion_times() -> [f64; 2] {
+    [0.0, 1.0]
+}
+
+impl ObjectDesc {
+    fn kind(&self) -> &'static str {
+        match self {
+            ObjectDesc::Sphere { .. } => "sphere",
+            ObjectDesc::Triangle { .. } => "triangle",
+            ObjectDesc::Plane { .. } => "plane",
+            ObjectDesc::Quad { .. } => "quad",
+            ObjectDesc::Disk { .. } => "disk",
+            ObjectDesc::Box { .. } => "box",
+            ObjectDesc::Cylinder { .. } => "cylinder",
+            ObjectDesc::Cone { .. } => "cone",
+            ObjectDesc::Paraboloid { .. } => "paraboloid",
+            ObjectDesc::Hyperboloid { .. } => "hyperboloid",
+            ObjectDesc::Torus { .. } => "torus",
+            ObjectDesc::Sdf { .. } => "sdf",
+            ObjectDesc::Heightfield { .. } => "heightfield",
+            ObjectDesc::Bezier { .. } => "bezier",
+            ObjectDesc::Mesh { .. } => "mesh",
+            ObjectDesc::Volume { .. } => "volume",
+            ObjectDesc::Csg { .. } => "csg",
+            ObjectDesc::Medium { .. } => "medium",
+        }
+    }
+
+    fn transform(&self) -> &[TransformDesc] {
+        match self {
+            ObjectDesc::Sphere { transform, .. }
+            | ObjectDesc::Triangle { transform, .. }
+            | ObjectDesc::Plane { transform, .. }
+            | ObjectDesc::Quad { transform, .. }
+            | ObjectDesc::Disk { transform, .. }
+            | ObjectDesc::Box { transform, .. }
+            | ObjectDesc::Cylinder { transform, .. }
+            | ObjectDesc::Cone { transform, .. }
+            | ObjectDesc::Paraboloid { transform, .. }
+            | ObjectDesc::Hyperboloid { transform, .. }
+            | ObjectDesc::Torus { transform, .. }
+            | ObjectDesc::Sdf { transform, .. }
+            | ObjectDesc::Heightfield { transform, .. }
+            | ObjectDesc::Bezier { transform, .. }
+            | ObjectDesc::Mesh { transform, .. }
+            | ObjectDesc::Volume { transform, .. }
+            | ObjectDesc::Csg { transform, .. } => transform,
+            ObjectDesc::Medium { .. } => &[],
+        }
+    }
+
+    fn keyframes(&self) -> &[KeyframeDesc] {
+        match self {
+            ObjectDesc::Sphere { keyframes, .. }
+            | ObjectDesc::Triangle { keyframes, .. }
+            | ObjectDesc::Plane { keyframes, .. }
+            | ObjectDesc::Quad { keyframes, .. }
+            | ObjectDesc::Disk { keyframes, .. }
+            | ObjectDesc::Box { keyframes, .. }
+            | ObjectDesc::Cylinder { keyframes, .. }
+            | ObjectDesc::Cone { keyframes, .. }
+            | ObjectDesc::Paraboloid { keyframes, .. }
+            | ObjectDesc::Hyperboloid { keyframes, .. }
+            | ObjectDesc::Torus { keyframes, .. }
+            | ObjectDesc::Sdf { keyframes, .. }
+            | ObjectDesc::Heightfield { keyframes, .. }
+            | ObjectDesc::Bezier { keyframes, .. }
+            | ObjectDesc::Mesh { keyframes, .. }
+            | ObjectDesc::Volume { keyframes, .. }
+            | ObjectDesc::Csg { keyframes, .. } => keyframes,
+            ObjectDesc::Medium { .. } => &[],
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyframeDesc {
+    time: f64,
+    transform: Vec<TransformDesc>,
+}
+
+// One step of an object's transform, written as a single key table like
+// { rotate_y = 45.0 }. Angles are in degrees
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum TransformDesc {
+    Translate([f64; 3]),
+    Scale(ScaleDesc),
+    RotateX(f64),
+    RotateY(f64),
+    RotateZ(f64),
+    Rotate { axis: [f64; 3], angle: f64 },
+    // Rows of an affine matrix, the last should be [0, 0, 0, 1]
+    Matrix([[f64; 4]; 4]),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ScaleDesc {
+    Uniform(f64),
+    Axes([f64; 3]),
+}
+
+// Triangles from one OBJ file and material, loaded once no matter how many
+// objects use them
+struct CachedMesh {
+    triangles: HittableList,
+    lights: HittableList,
+    // Whether it encloses a solid, for csgs
+    closed: bool,
+    // Only built when the mesh gets instanced through a transform
+    bvh: OnceCell<Arc<dyn Hittable>>,
+}
+
+fn vec3(a: [f64; 3]) -> Vec3 {
+    Vec3::new(a[0], a[1], a[2])
+}
+
+// Builds errors for one file, so each check only needs to say what went wrong
+struct Validator<'a> {
+    path: &'a Path,
+    meshes: RefCell<HashMap<(PathBuf, String), Rc<CachedMesh>>>,
+    grids: RefCell<HashMap<PathBuf, Arc<VoxelGrid>>>,
+}
+
+impl Validator<'_> {
+    fn error(&self, entry: &str, message: String) -> SceneError {
+        SceneError {
+            path: self.path.to_path_buf(),
+            entry: Some(entry.to_string()),
+            message,
+        }
+    }
+
+    fn check(&self, ok: bool, entry: &str, message: &str) -> Result<(), SceneError> {
+        if ok {
+            Ok(())
+        } else {
+            Err(self.error(entry, message.to_string()))
+        }
+    }
+
+    fn image(&self, desc: &ImageDesc) -> Result<Image, SceneError> {
+        let entry = "image";
+        self.check(desc.width > 0, entry, "width must be positive")?;
+        self.check(desc.samples > 0, entry, "samples must be positive")?;
+
+        let height = match (desc.height, desc.aspect_ratio) {
+            (Some(_), Some(_)) => {
+                return Err(self.error(
+                    entry,
+                    "give either height or aspect_ratio, not both".to_string(),
+                ))
+            }
+            (Some(height), None) => height,
+            (None, Some(aspect_ratio)) => {
+                self.check(aspect_ratio > 0.0, entry, "aspect_ratio must be positive")?;
+                (desc.width as f64 / aspect_ratio) as u32
+            }
+            (None, None) => {
+                return Err(self.error(entry, "missing height or aspect_ratio".to_string()))
+            }
+        };
+        self.check(height > 0, entry, "height must be positive")?;
+
+        Ok(Image {
+            width: desc.width,
+            height,
+            samples: desc.samples,
+            bounce_depth: desc.bounce_depth,
+            roulette_depth: desc.roulette_depth,
+        })
+    }
+
+    fn camera(&self, desc: &CameraDesc) -> Result<CameraSettings, SceneError> {
+        let entry = "camera";
+        let position = vec3(desc.position);
+        let look_at = vec3(desc.look_at);
+        let up = vec3(desc.up);
+
+        self.check(
+            !(position - look_at).near_zero(),
+            entry,
+            "position and look_at must differ",
+        )?;
+        self.check(
+            !cross(&up, &(position - look_at)).near_zero(),
+            entry,
+            "up must not be parallel to the view direction",
+        )?;
+        self.check(
+            desc.vfov > 0.0 && desc.vfov < 180.0,
+            entry,
+            "vfov must be between 0 and 180 degrees",
+        )?;
+        self.check(desc.aperture >= 0.0, entry, "aperture must not be negative")?;
+
+        let focus_dist = desc.focus_dist.unwrap_or((position - look_at).length());
+        self.check(focus_dist > 0.0, entry, "focus_dist must be positive")?;
+
+        let [shutter_open, shutter_close] = desc.shutter;
+        self.check(
+            shutter_open <= shutter_close,
+            entry,
+            "shutter must open before it closes",
+        )?;
+
+        Ok(CameraSettings {
+            position,
+            look_at,
+            up,
+            vfov: desc.vfov,
+            aperture: desc.aperture,
+            focus_dist,
+            shutter_open,
+            shutter_close,
+        })
+    }
+
+    fn background(&self, desc: &BackgroundDesc) -> Result<Arc<dyn Background>, SceneError> {
+        let entry = "background";
+
+        Ok(match desc {
+            BackgroundDesc::Solid { color } => Arc::new(SolidBackground {
+                color: vec3(*color),
+            }),
+            BackgroundDesc::Gradient { bottom, top } => Arc::new(GradientBackground {
+                bottom: vec3(*bottom),
+                top: vec3(*top),
+            }),
+            BackgroundDesc::Environment {
+                file,
+                rotation,
+                intensity,
+            } => {
+                self.check(*intensity >= 0.0, entry, "intensity must not be negative")?;
+                let map = EnvironmentMap::load(&self.relative(file), *rotation, *intensity)
+                    .map_err(|why| self.error(entry, why))?;
+                Arc::new(map)
+            }
+        })
+    }
+
+    // Paths in a scene are relative to the scene file
+    fn relative(&self, file: &str) -> PathBuf {
+        self.path
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(file)
+    }
+
+    fn texture(&self, name: &str, desc: &TextureDesc) -> Result<Arc<dyn Texture>, SceneError> {
+        let entry = format!("textures.{}", name);
+
+        Ok(match desc {
+            TextureDesc::Checker {
+                even,
+                odd,
+                scale,
+                uv,
+            } => {
+                self.check(*scale > 0.0, &entry, "scale must be positive")?;
+                if *uv {
+                    Arc::new(UvCheckerTexture::new(vec3(*even), vec3(*odd), *scale))
+                } else {
+                    Arc::new(CheckerTexture::new(vec3(*even), vec3(*odd), *scale))
+                }
+            }
+            TextureDesc::Image { file } => {
+                let image = ImageTexture::load(&self.relative(file))
+                    .map_err(|why| self.error(&entry, why))?;
+                Arc::new(image)
+            }
+            TextureDesc::Noise {
+                seed,
+                scale,
+                pattern,
+                octaves,
+                lacunarity,
+                gain,
+                low,
+                high,
+            } => {
+                self.check(*scale > 0.0, &entry, "scale must be positive")?;
+                let pattern = match pattern.as_str() {
+                    "plain" => NoisePattern::Plain,
+                    "fbm" => NoisePattern::Fbm,
+                    "turbulence" => NoisePattern::Turbulence,
+                    other => {
+                        return Err(self.error(
+                            &entry,
+                            format!("unknown pattern '{}', use plain, fbm or turbulence", other),
+                        ))
+                    }
+                };
+                Arc::new(NoiseTexture {
+                    noise: Perlin::new(*seed),
+                    pattern,
+                    scale: *scale,
+                    octaves: self.octaves(&entry, *octaves, *lacunarity, *gain)?,
+                    low: vec3(*low),
+                    high: vec3(*high),
+                })
+            }
+            TextureDesc::Marble {
+                seed,
+                scale,
+                octaves,
+                lacunarity,
+                gain,
+                turbulence,
+                low,
+                high,
+            } => {
+                self.check(*scale > 0.0, &entry, "scale must be positive")?;
+                Arc::new(MarbleTexture {
+                    noise: Perlin::new(*seed),
+                    scale: *scale,
+                    octaves: self.octaves(&entry, *octaves, *lacunarity, *gain)?,
+                    turbulence: *turbulence,
+                    low: vec3(*low),
+                    high: vec3(*high),
+                })
+            }
+            TextureDesc::Wood {
+                seed,
+                scale,
+                octaves,
+                lacunarity,
+                gain,
+                turbulence,
+                low,
+                high,
+            } => {
+                self.check(*scale > 0.0, &entry, "scale must be positive")?;
+                Arc::new(WoodTexture {
+                    noise: Perlin::new(*seed),
+                    scale: *scale,
+                    octaves: self.octaves(&entry, *octaves, *lacunarity, *gain)?,
+                    turbulence: *turbulence,
+                    low: vec3(*low),
+                    high: vec3(*high),
+                })
+            }
+            TextureDesc::Worley {
+                seed,
+                scale,
+                feature,
+                low,
+                high,
+            } => {
+                self.check(*scale > 0.0, &entry, "scale must be positive")?;
+                let feature = match feature.as_str() {
+                    "f1" => WorleyFeature::F1,
+                    "f2" => WorleyFeature::F2,
+                    "f2_minus_f1" => WorleyFeature::F2MinusF1,
+                    other => {
+                        return Err(self.error(
+                            &entry,
+                            format!("unknown feature '{}', use f1, f2 or f2_minus_f1", other),
+                        ))
+                    }
+                };
+                Arc::new(WorleyTexture {
+                    noise: Worley::new(*seed),
+                    feature,
+                    scale: *scale,
+                    low: vec3(*low),
+                    high: vec3(*high),
+                })
+            }
+        })
+    }
+
+    fn octaves(
+        &self,
+        entry: &str,
+        count: u32,
+        lacunarity: f64,
+        gain: f64,
+    ) -> Result<Octaves, SceneError> {
+        self.check(count > 0, entry, "octaves must be positive")?;
+        self.check(lacunarity > 0.0, entry, "lacunarity must be positive")?;
+        self.check(gain > 0.0, entry, "gain must be positive")?;
+
+        Ok(Octaves {
+            count,
+            lacunarity,
+            gain,
+        })
+    }
+
+    fn albedo(
+        &self,
+        entry: &str,
+        desc: &AlbedoDesc,
+        textures: &BTreeMap<String, Arc<dyn Texture>>,
+    ) -> Result<Arc<dyn Texture>, SceneError> {
+        match desc {
+            AlbedoDesc::Color(color) => Ok(Arc::new(SolidColor {
+                color: vec3(*color),
+            })),
+            AlbedoDesc::Texture(name) => textures
+                .get(name)
+                .cloned()
+                .ok_or_else(|| self.error(entry, format!("unknown texture '{}'", name))),
+        }
+    }
+
+    fn material(
+        &self,
+        name: &str,
+        desc: &MaterialDesc,
+        textures: &BTreeMap<String, Arc<dyn Texture>>,
+    ) -> Result<Arc<dyn Material>, SceneError> {
+        let entry = format!("materials.{}", name);
+
+        Ok(match desc {
+            MaterialDesc::Lambertian { albedo } => {
+                Arc::new(Lambertian::textured(self.albedo(&entry, albedo, textures)?))
+            }
+            MaterialDesc::Metal { albedo, fuzz } => {
+                self.check(
+                    (0.0..=1.0).contains(fuzz),
+                    &entry,
+                    "fuzz must be between 0 and 1",
+                )?;
+                Arc::new(Metal::textured(
+                    self.albedo(&entry, albedo, textures)?,
+                    *fuzz,
+                ))
+            }
+            MaterialDesc::Dielectric { ior } => {
+                self.check(*ior > 0.0, &entry, "ior must be positive")?;
+                Arc::new(Dielectric::new(*ior))
+            }
+            MaterialDesc::DiffuseLight { emit, intensity } => {
+                let emit = vec3(*emit) * *intensity;
+                self.check(
+                    emit.x >= 0.0 && emit.y >= 0.0 && emit.z >= 0.0,
+                    &entry,
+                    "emitted light must not be negative",
+                )?;
+                Arc::new(DiffuseLight { emit })
+            }
+            MaterialDesc::Isotropic { albedo } => {
+                Arc::new(Isotropic::textured(self.albedo(&entry, albedo, textures)?))
+            }
+            MaterialDesc::HenyeyGreenstein { albedo, g } => {
+                self.check(*g > -1.0 && *g < 1.0, &entry, "g must be between -1 and 1")?;
+                Arc::new(HenyeyGreenstein::textured(
+                    self.albedo(&entry, albedo, textures)?,
+                    *g,
+                ))
+            }
+        })
+    }
+
+    fn transform_steps(
+        &self,
+        entry: &str,
+        steps: &[TransformDesc],
+    ) -> Result<Vec<TransformStep>, SceneError> {
+        let axis = |x, y, z| Vec3::new(x, y, z);
+
+        steps
+            .iter()
+            .map(|step| {
+                Ok(match step {
+                    TransformDesc::Translate(offset) => TransformStep::Translate(vec3(*offset)),
+                    TransformDesc::Scale(ScaleDesc::Uniform(s)) => {
+                        TransformStep::Scale(Vec3::new(*s, *s, *s))
+                    }
+                    TransformDesc::Scale(ScaleDesc::Axes(s)) => TransformStep::Scale(vec3(*s)),
+                    TransformDesc::RotateX(angle) => {
+                        TransformStep::Rotate(axis(1.0, 0.0, 0.0), deg_to_rad(*angle))
+                    }
+                    TransformDesc::RotateY(angle) => {
+                        TransformStep::Rotate(axis(0.0, 1.0, 0.0), deg_to_rad(*angle))
+                    }
+                    TransformDesc::RotateZ(angle) => {
+                        TransformStep::Rotate(axis(0.0, 0.0, 1.0), deg_to_rad(*angle))
+                    }
+                    TransformDesc::Rotate { axis, angle } => {
+                        self.check(
+                            !vec3(*axis).near_zero(),
+                            entry,
+                            "rotation axis must not be zero",
+                        )?;
+                        TransformStep::Rotate(vec3(*axis), deg_to_rad(*angle))
+                    }
+                    TransformDesc::Matrix(m) => {
+                        self.check(
+                            m[3] == [0.0, 0.0, 0.0, 1.0],
+                            entry,
+                            "matrix must be affine, with a last row of [0, 0, 0, 1]",
+                        )?;
+                        TransformStep::Matrix(Mat4::new(*m))
+                    }
+                })
+            })
+            .collect()
+    }
+
+    // Steps are applied in the order they're listed
+    fn transform(&self, entry: &str, steps: &[TransformDesc]) -> Result<Option<Mat4>, SceneError> {
+        if steps.is_empty() {
+            return Ok(None);
+        }
+
+        let transform = compose(&self.transform_steps(entry, steps)?);
+        self.check(
+            transform.inverse().is_some(),
+            entry,
+            "transform must be invertible",
+        )?;
+        Ok(Some(transform))
+    }
+
+    fn keyframes(
+        &self,
+        entry: &str,
+        descs: &[KeyframeDesc],
+    ) -> Result<Option<Vec<Keyframe>>, SceneError> {
+        if descs.is_empty() {
+            return Ok(None);
+        }
+
+        let mut keyframes: Vec<Keyframe> = Vec::new();
+        for desc in descs {
+            let steps = self.transform_steps(entry, &desc.transform)?;
+            if let Some(previous) = keyframes.last() {
+                self.check(
+                    previous.time <= desc.time,
+                    entry,
+                    "keyframes must be in time order",
+                )?;
+                self.check(
+                    previous.steps.len() == steps.len()
+                        && previous
+                            .steps
+                            .iter()
+                            .zip(steps.iter())
+                            .all(|(a, b)| a.lerp(b, 0.0).is_some()),
+                    entry,
+                    "keyframes must have the same kinds of steps in the same order",
+                )?;
+            }
+            keyframes.push(Keyframe {
+                time: desc.time,
+                steps,
+            });
+        }
+
+        Ok(Some(keyframes))
+    }
+
+    fn mesh(
+        &self,
+        entry: &str,
+        file: &str,
+        name: &str,
+        mat: Arc<dyn Material>,
+    ) -> Result<Rc<CachedMesh>, SceneError> {
+        let path = self.relative(file);
+        let key = (path.clone(), name.to_string());
+        if let Some(mesh) = self.meshes.borrow().get(&key) {
+            return Ok(mesh.clone());
+        }
+
+        let meshes =
+            load_obj_meshes(&path, mat).map_err(|why| self.error(entry, why.to_string()))?;
+        let closed = is_closed(&meshes);
+        let mut triangles = HittableList::new();
+        let mut lights = HittableList::new();
+        for mesh in meshes {
+            let faces = TriangleMesh::triangles(mesh.clone());
+            if mesh.mat.is_emissive() {
+                lights.objects.extend(faces.objects.iter().cloned());
+            }
+            triangles.objects.extend(faces.objects);
+        }
+        // Meshes go in a BVH when transformed or used as a boundary, which
+        // needs at least one triangle
+        self.check(!triangles.objects.is_empty(), entry, "file has no faces")?;
+
+        let mesh = Rc::new(CachedMesh {
+            triangles,
+            lights,
+            closed,
+            bvh: OnceCell::new(),
+        });
+        self.meshes.borrow_mut().insert(key, mesh.clone());
+        Ok(mesh)
+    }
+
+    fn grid(&self, entry: &str, file: &str) -> Result<Arc<VoxelGrid>, SceneError> {
+        let path = self.relative(file);
+        if let Some(grid) = self.grids.borrow().get(&path) {
+            return Ok(grid.clone());
+        }
+
+        let grid = Arc::new(VoxelGrid::load(&path).map_err(|why| self.error(entry, why))?);
+        self.grids.borrow_mut().insert(path, grid.clone());
+        Ok(grid)
+    }
+
+    fn sdf(&self, entry: &str, desc: &SdfDesc) -> Result<Sdf, SceneError> {
+        let child = |field: &str, desc: &SdfDesc| self.sdf(&format!("{}.{}", entry, field), desc);
+        let sdf = match desc {
+            SdfDesc::Sphere { radius } => {
+                self.check(*radius > 0.0, entry, "radius must be positive")?;
+                Sdf::Sphere { radius: *radius }
+            }
+            SdfDesc::Box { size } => {
+                self.check(
+                    size.iter().all(|s| *s > 0.0),
+                    entry,
+                    "size must be positive along every axis",
+                )?;
+                Sdf::Box {
+                    half_extents: vec3(*size) / 2.0,
+                }
+            }
+            SdfDesc::Torus {
+                major_radius,
+                minor_radius,
+            } => {
+                self.check(*major_radius > 0.0, entry, "major_radius must be positive")?;
+                self.check(*minor_radius > 0.0, entry, "minor_radius must be positive")?;
+                Sdf::Torus {
+                    major_radius: *major_radius,
+                    minor_radius: *minor_radius,
+                }
+            }
+            SdfDesc::Capsule { a, b, radius } => {
+                self.check(*radius > 0.0, entry, "radius must be positive")?;
+                self.check(a != b, entry, "a and b must be different points")?;
+                Sdf::Capsule {
+                    a: vec3(*a),
+                    b: vec3(*b),
+                    radius: *radius,
+                }
+            }
+            SdfDesc::SmoothUnion { a, b, k } => {
+                self.check(*k > 0.0, entry, "k must be positive")?;
+                Sdf::SmoothUnion {
+                    a: Box::new(child("a", a)?),
+                    b: Box::new(child("b", b)?),
+                    k: *k,
+                }
+            }
+            SdfDesc::Subtraction { a, b } => Sdf::Subtraction {
+                a: Box::new(child("a", a)?),
+                b: Box::new(child("b", b)?),
+            },
+            SdfDesc::Repeat { shape, period } => {
+                self.check(
+                    period.iter().all(|p| *p >= 0.0),
+                    entry,
+                    "period must not be negative",
+                )?;
+                Sdf::Repeat {
+                    sdf: Box::new(child("shape", shape)?),
+                    period: vec3(*period),
+                }
+            }
+            SdfDesc::Twist { shape, rate } => Sdf::twist(child("shape", shape)?, deg_to_rad(*rate))
+                .map_err(|why| self.error(entry, why))?,
+            SdfDesc::Translate { shape, offset } => Sdf::Translate {
+                sdf: Box::new(child("shape", shape)?),
+                offset: vec3(*offset),
+            },
+        };
+        Ok(sdf)
+    }
+
+    fn object(
+        &self,
+        index: usize,
+        desc: &ObjectDesc,
+        materials: &BTreeMap<String, Arc<dyn Material>>,
+        world: &mut HittableList,
+        lights: &mut HittableList,
+    ) -> Result<(), SceneError> {
+        let entry = format!("objects[{}] ({})", index, desc.kind());
+
+        let (boundary, density) = match desc {
+            ObjectDesc::Medium { boundary, density } => (boundary, *density),
+            _ => return self.shape(&entry, desc, materials, true, world, lights),
+        };
+
+        self.check(density > 0.0, &entry, "density must be positive")?;
+        let boundary_entry = format!("{} boundary ({})", entry, boundary.kind());
+        let name = match boundary.as_ref() {
+            ObjectDesc::Sphere { material, .. }
+            | ObjectDesc::Triangle { material, .. }
+            | ObjectDesc::Plane { material, .. }
+            | ObjectDesc::Quad { material, .. }
+            | ObjectDesc::Disk { material, .. }
+            | ObjectDesc::Box { material, .. }
+            | ObjectDesc::Cylinder { material, .. }
+            | ObjectDesc::Cone { material, .. }
+            | ObjectDesc::Paraboloid { material, .. }
+            | ObjectDesc::Hyperboloid { material, .. }
+            | ObjectDesc::Torus { material, .. }
+            | ObjectDesc::Sdf { material, .. }
+            | ObjectDesc::Heightfield { material, .. }
+            | ObjectDesc::Bezier { material, .. }
+            | ObjectDesc::Mesh { material, .. } => material,
+            ObjectDesc::Volume { .. } | ObjectDesc::Medium { .. } => {
+                return Err(self.error(&entry, "boundary must be a surface".to_string()))
+            }
+            ObjectDesc::Csg { .. } => {
+                return Err(self.error(
+                    &entry,
+                    "boundary must have a single material, not be a csg".to_string(),
+                ))
+            }
+        };
+        let phase_function = materials
+            .get(name)
+            .cloned()
+            .ok_or_else(|| self.error(&boundary_entry, format!("unknown material '{}'", name)))?;
+        self.check(
+            phase_function.is_phase_function(),
+            &boundary_entry,
+            "material must be isotropic or henyey_greenstein",
+        )?;
+        if matches!(boundary.as_ref(), ObjectDesc::Plane { .. }) {
+            return Err(self.error(&entry, "boundary must be bounded".to_string()));
+        }
+        self.solid(&boundary_entry, boundary, materials)?;
+
+        // Built on its own, so any lights it has are left out
+        let mut shape = HittableList::new();
+        self.shape(
+            &boundary_entry,
+            boundary,
+            materials,
+            false,
+            &mut shape,
+            &mut HittableList::new(),
+        )?;
+        world.add(Arc::new(ConstantMedium::with_phase(
+            shape.objects[0].clone(),
+            density,
+            phase_function,
+        )));
+
+        Ok(())
+    }
+
+    // Checks that an object encloses a solid, so rays crossing its surface
+    // go in and out of it in turn. Planes count as the half space behind
+    // them
+    fn solid(
+        &self,
+        entry: &str,
+        desc: &ObjectDesc,
+        materials: &BTreeMap<String, Arc<dyn Material>>,
+    ) -> Result<(), SceneError> {
+        match desc {
+            ObjectDesc::Sphere { .. }
+            | ObjectDesc::Plane { .. }
+            | ObjectDesc::Box { .. }
+            | ObjectDesc::Torus { .. }
+            | ObjectDesc::Sdf { .. }
+            | ObjectDesc::Csg { .. } => Ok(()),
+            ObjectDesc::Cylinder { capped, .. }
+            | ObjectDesc::Cone { capped, .. }
+            | ObjectDesc::Paraboloid { capped, .. }
+            | ObjectDesc::Hyperboloid { capped, .. } => {
+                self.check(*capped, entry, "must be capped to enclose a solid")
+            }
+            ObjectDesc::Mesh {
+                file,
+                material: name,
+                ..
+            } => {
+                let mat = materials
+                    .get(name)
+                    .cloned()
+                    .ok_or_else(|| self.error(entry, format!("unknown material '{}'", name)))?;
+                let mesh = self.mesh(entry, file, name, mat)?;
+                self.check(
+                    mesh.closed,
+                    entry,
+                    "mesh must be closed, with every edge shared by two faces",
+                )
+            }
+            ObjectDesc::Triangle { .. }
+            | ObjectDesc::Quad { .. }
+            | ObjectDesc::Disk { .. }
+            | ObjectDesc::Heightfield { .. }
+            | ObjectDesc::Bezier { .. } => Err(self.error(
+                entry,
+                "must enclose a solid, not be an open surface".to_string(),
+            )),
+            ObjectDesc::Volume { .. } | ObjectDesc::Medium { .. } => {
+                Err(self.error(entry, "must be a surface".to_string()))
+            }
+        }
+    }
+
+    // Adds a surface to world, along with the parts of it that are lights.
+    // When flatten is set meshes staying put are added triangle by triangle
+    // for the scene's BVH, otherwise every shape is a single hittable
+    fn shape(
+        &self,
+        entry: &str,
+        desc: &ObjectDesc,
+        materials: &BTreeMap<String, Arc<dyn Material>>,
+        flatten: bool,
+        world: &mut HittableList,
+        lights: &mut HittableList,
+    ) -> Result<(), SceneError> {
+        let entry = entry.to_string();
+        let material = |name: &String| {
+            materials
+                .get(name)
+                .cloned()
+                .ok_or_else(|| self.error(&entry, format!("unknown material '{}'", name)))
+        };
+        let transform = self.transform(&entry, desc.transform())?;
+        let keyframes = self.keyframes(&entry, desc.keyframes())?;
+        self.check(
+            transform.is_none() || keyframes.is_none(),
+            &entry,
+            "give either transform or keyframes, not both",
+        )?;
+        let placed = transform.is_some() || keyframes.is_some();
+
+        // Static surfaces are lights too if their material is emissive
+        let surface = |object: Arc<dyn Hittable>, mat: &Arc<dyn Material>| {
+            let lights = if mat.is_emissive() {
+                vec![object.clone()]
+            } else {
+                Vec::new()
+            };
+            (object, lights)
+        };
+
+        // The object in its own space, as a single hittable for placing
+        // with the transform, and the parts of it that are lights
+        let (object, object_lights): (Arc<dyn Hittable>, Vec<Arc<dyn Hittable>>) = match desc {
+            ObjectDesc::Sphere {
+                center,
+                radius,
+                material: name,
+                center_end,
+                motion_times: [time0, time1],
+                ..
+            } => {
+                self.check(*radius != 0.0, &entry, "radius must not be zero")?;
+                let mat = material(name)?;
+                let sphere: Arc<dyn Hittable> = match center_end {
+                    Some(center_end) => {
+                        self.check(
+                            time0 < time1,
+                            &entry,
+                            "motion_times must start before they end",
+                        )?;
+                        Arc::new(Sphere::moving(
+                            vec3(*center),
+                            *time0,
+                            vec3(*center_end),
+                            *time1,
+                            *radius,
+                            mat.clone(),
+                        ))
+                    }
+                    None => Arc::new(Sphere::new(vec3(*center), *radius, mat.clone())),
+                };
+                // Light sampling doesn't know the time, so only static
+                // lights can be sampled
+                let lights = if mat.is_emissive() && center_end.is_none() {
+                    vec![sphere.clone()]
+                } else {
+                    Vec::new()
+                };
+                (sphere, lights)
+            }
+            ObjectDesc::Triangle {
+                vertices,
+                material: name,
+                ..
+            } => {
+                let [v0, v1, v2] = vertices.map(vec3);
+                self.check(
+                    !cross(&(v1 - v0), &(v2 - v0)).near_zero(),
+                    &entry,
+                    "vertices must not be collinear",
+                )?;
+                let mat = material(name)?;
+                surface(Arc::new(Triangle::new(v0, v1, v2, mat.clone())), &mat)
+            }
+            ObjectDesc::Plane {
+                point,
+                normal,
+                material: name,
+                uv_scale,
+                ..
+            } => {
+                self.check(
+                    !vec3(*normal).near_zero(),
+                    &entry,
+                    "normal must not be zero",
+                )?;
+                self.check(*uv_scale > 0.0, &entry, "uv_scale must be positive")?;
+                // Infinitely large, so there's no sampling it as a light
+                let plane = Plane::new(vec3(*point), vec3(*normal), material(name)?)
+                    .with_uv_scale(*uv_scale);
+                (Arc::new(plane), Vec::new())
+            }
+            ObjectDesc::Quad {
+                corner,
+                u,
+                v,
+                material: name,
+                ..
+            } => {
+                let (u, v) = (vec3(*u), vec3(*v));
+                self.check(
+                    !cross(&u, &v).near_zero(),
+                    &entry,
+                    "u and v must not be parallel",
+                )?;
+                let mat = material(name)?;
+                surface(Arc::new(Quad::new(vec3(*corner), u, v, mat.clone())), &mat)
+            }
+            ObjectDesc::Disk {
+                center,
+                normal,
+                radius,
+                material: name,
+                ..
+            } => {
+                self.check(*radius > 0.0, &entry, "radius must be positive")?;
+                self.check(
+                    !vec3(*normal).near_zero(),
+                    &entry,
+                    "normal must not be zero",
+                )?;
+                let mat = material(name)?;
+                let disk = Quad::disk(vec3(*center), vec3(*normal), *radius, mat.clone());
+                surface(Arc::new(disk), &mat)
+            }
+            ObjectDesc::Box {
+                min,
+                max,
+                material: name,
+                ..
+            } => {
+                self.check(
+                    (0..3).all(|i| min[i] < max[i]),
+                    &entry,
+                    "min must be below max along every axis",
+                )?;
+                let mat = material(name)?;
+                surface(
+                    Arc::new(Cuboid::new(vec3(*min), vec3(*max), mat.clone())),
+                    &mat,
+                )
+            }
+            // These can't be sampled, so emissive ones only light what
+            // happens to bounce into them
+            ObjectDesc::Cylinder {
+                radius,
+                height,
+                material: name,
+                capped,
+                ..
+            } => {
+                self.check(*radius > 0.0, &entry, "radius must be positive")?;
+                self.check(*height > 0.0, &entry, "height must be positive")?;
+                let cylinder = Cylinder::new(*radius, 0.0, *height, *capped, material(name)?);
+                (Arc::new(cylinder), Vec::new())
+            }
+            ObjectDesc::Cone {
+                radius,
+                height,
+                material: name,
+                capped,
+                ..
+            } => {
+                self.check(*radius > 0.0, &entry, "radius must be positive")?;
+                self.check(*height > 0.0, &entry, "height must be positive")?;
+                let cone = Cone::new(*radius, *height, *capped, material(name)?);
+                (Arc::new(cone), Vec::new())
+            }
+            ObjectDesc::Paraboloid {
+                radius,
+                height,
+                material: name,
+                capped,
+                ..
+            } => {
+                self.check(*radius > 0.0, &entry, "radius must be positive")?;
+                self.check(*height > 0.0, &entry, "height must be positive")?;
+                let paraboloid = Paraboloid::new(*radius, *height, *capped, material(name)?);
+                (Arc::new(paraboloid), Vec::new())
+            }
+            ObjectDesc::Hyperboloid {
+                waist,
+                radius,
+                height,
+                material: name,
+                capped,
+                ..
+            } => {
+                self.check(*waist > 0.0, &entry, "waist must be positive")?;
+                self.check(*radius > 0.0, &entry, "radius must be positive")?;
+                self.check(*height > 0.0, &entry, "height must be positive")?;
+                let hyperboloid =
+                    Hyperboloid::new(*waist, *radius, *height / 2.0, *capped, material(name)?);
+                (Arc::new(hyperboloid), Vec::new())
+            }
+            ObjectDesc::Torus {
+                major_radius,
+                minor_radius,
+                material: name,
+                ..
+            } => {
+                self.check(*major_radius > 0.0, &entry, "major_radius must be positive")?;
+                self.check(*minor_radius > 0.0, &entry, "minor_radius must be positive")?;
+                let torus = Torus::new(*major_radius, *minor_radius, material(name)?);
+                (Arc::new(torus), Vec::new())
+            }
+            ObjectDesc::Sdf {
+                shape,
+                material: name,
+                ..
+            } => {
+                let sdf = self.sdf(&format!("{} shape", entry), shape)?;
+                (Arc::new(SdfObject::new(sdf, material(name)?)), Vec::new())
+            }
+            ObjectDesc::Heightfield {
+                file,
+                material: name,
+                ..
+            } => {
+                let map =
+                    HeightMap::load(&self.relative(file)).map_err(|why| self.error(&entry, why))?;
+                let heightfield = Heightfield::new(Arc::new(map), material(name)?);
+                (Arc::new(heightfield), Vec::new())
+            }
+            ObjectDesc::Bezier {
+                file,
+                material: name,
+                ..
+            } => {
+                let patches =
+                    load_patches(&self.relative(file)).map_err(|why| self.error(&entry, why))?;
+                self.check(!patches.is_empty(), &entry, "file has no patches")?;
+                let mat = material(name)?;
+                let mut model = HittableList::new();
+                for control in patches {
+                    model.add(Arc::new(BezierPatch::new(control, mat.clone())));
+                }
+                (Arc::new(BvhNode::new(&model)), Vec::new())
+            }
+            ObjectDesc::Mesh {
+                file,
+                material: name,
+                ..
+            } => {
+                let mesh = self.mesh(&entry, file, name, material(name)?)?;
+
+                // Meshes staying put go straight into the scene's BVH,
+                // instances share one of their own
+                if flatten && !placed {
+                    world.objects.extend(mesh.triangles.objects.iter().cloned());
+                    lights.objects.extend(mesh.lights.objects.iter().cloned());
+                    return Ok(());
+                }
+
+                let bvh = mesh
+                    .bvh
+                    .get_or_init(|| Arc::new(BvhNode::new(&mesh.triangles)))
+                    .clone();
+                (bvh, mesh.lights.objects.clone())
+            }
+            ObjectDesc::Volume {
+                file,
+                material: name,
+                density,
+                ..
+            } => {
+                self.check(*density >= 0.0, &entry, "density must not be negative")?;
+                let grid = self.grid(&entry, file)?;
+                let volume: Arc<dyn Hittable> =
+                    Arc::new(GridMedium::new(grid, *density, material(name)?));
+                (volume, Vec::new())
+            }
+            ObjectDesc::Csg {
+                operation, objects, ..
+            } => {
+                self.check(objects.len() >= 2, &entry, "needs at least two objects")?;
+
+                // Built on their own, so any lights they have are left out
+                let mut parts = HittableList::new();
+                for (i, child) in objects.iter().enumerate() {
+                    let child_entry = format!("{} objects[{}] ({})", entry, i, child.kind());
+                    self.solid(&child_entry, child, materials)?;
+                    self.shape(
+                        &child_entry,
+                        child,
+                        materials,
+                        false,
+                        &mut parts,
+                        &mut HittableList::new(),
+                    )?;
+                }
+
+                let op = match operation {
+                    CsgOpDesc::Union => CsgOp::Union,
+                    CsgOpDesc::Intersection => CsgOp::Intersection,
+                    CsgOpDesc::Difference => CsgOp::Difference,
+                };
+                // Later objects are combined in one at a time, so a
+                // difference takes all of them away from the first
+                let csg = parts
+                    .objects
+                    .into_iter()
+                    .reduce(|a, b| Arc::new(Csg::new(op, a, b)))
+                    .unwrap();
+                (csg, Vec::new())
+            }
+            ObjectDesc::Medium { .. } => unreachable!("media are built by object"),
+        };
+
+        if let Some(keyframes) = keyframes {
+            // Moving, so its lights can't be sampled either
+            world.add(Arc::new(Animated::new(object, keyframes).unwrap()));
+            return Ok(());
+        }
+
+        match transform {
+            Some(transform) => {
+                // Invertibility was checked when the transform was built
+                let place = |object| Arc::new(Transformed::new(object, transform).unwrap());
+                world.add(place(object));
+                for light in object_lights {
+                    lights.add(place(light));
+                }
+            }
+            None => {
+                world.add(object);
+                lights.objects.extend(object_lights);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub fn load_scene(path: &Path) -> Result<Scene, SceneError> {
+    let source = fs::read_to_string(path).map_err(|why| SceneError {
+        path: path.to_path_buf(),
+        entry: None,
+        message: format!("could not read file: {}", why),
+    })?;
+
+    // Syntax and type errors come with their own line and key information
+    let desc: SceneDesc = toml::from_str(&source).map_err(|why| SceneError {
+        path: path.to_path_buf(),
+        entry: None,
+        message: why.to_string(),
+    })?;
+
+    let validator = Validator {
+        path,
+        meshes: RefCell::new(HashMap::new()),
+        grids: RefCell::new(HashMap::new()),
+    };
+    let image = validator.image(&desc.image)?;
+    let camera = validator.camera(&desc.camera)?;
+    let background = match &desc.background {
+        Some(background) => validator.background(background)?,
+        None => Arc::new(GradientBackground::sky()),
+    };
+
+    let mut textures = BTreeMap::new();
+    for (name, texture) in &desc.textures {
+        textures.insert(name.clone(), validator.texture(name, texture)?);
+    }
+
+    let mut materials = BTreeMap::new();
+    for (name, mat) in &desc.materials {
+        materials.insert(name.clone(), validator.material(name, mat, &textures)?);
+    }
+
+    let mut world = HittableList::new();
+    let mut lights = HittableList::new();
+    for (index, object) in desc.objects.iter().enumerate() {
+        validator.object(index, object, &materials, &mut world, &mut lights)?;
+    }
+
+    if world.objects.is_empty() {
+        return Err(SceneError {
+            path: path.to_path_buf(),
+            entry: None,
+            message: "scene has no objects".to_string(),
+        });
+    }
+
+    Ok(Scene {
+        image,
+        camera,
+        world,
+        lights,
+        background,
+    })
+}
+
+pub const BUILTIN_SCENES: &[&str] = &["random_balls"];
+
+pub fn builtin_scene(name: &str) -> Option<Scene> {
+    match name {
+        "random_balls" => Some(random_balls()),
+        _ => None,
+    }
+}
+
+// The final scene from Ray Tracing in One Weekend, a few large spheres
+// surrounded by a grid of small random ones
+pub fn random_balls() -> Scene {
+    let aspect_ratio = 16.0 / 9.0;
+    let image_width = 400;
+    let image = Image {
+        width: image_width,
+        height: (image_width as f64 / aspect_ratio) as u32,
+        samples: 100,
+        bounce_depth: 50,
+        roulette_depth: 5,
+    };
+
+    // Materials
+    let material_ground = Arc::new(Lambertian::new(0.8, 0.8, 0.6));
+    let material_diffuse = Arc::new(Lambertian::new(0.7, 0.3, 0.3));
+    let material_metal = Arc::new(Metal::new(0.8, 0.8, 0.8, 0.3));
+    let material_glass = Arc::new(Dielectric::new(1.5));
+
+    // World
+    let mut world = HittableList::new();
+    world.add(Arc::new(Plane::new(
+        Point3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        material_ground.clone(),
+    )));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(-4.0, 1.0, 0.0),
+        1.0,
+        material_diffuse.clone(),
+    )));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(0.0, 1.0, 0.0),
+        1.0,
+        material_glass.clone(),
+    )));
+    world.add(Arc::new(Sphere::new(
+        Point3::new(4.0, 1.0, 0.0),
+        1.0,
+        material_metal.clone(),
+    )));
+
+    // Generate balls
+    let radius = 0.2;
+    for x in -11..11 {
+        for z in -11..11 {
+            let center = Point3::new(
+                x as f64 + 0.9 * rand_unit(),
+                0.2,
+                z as f64 + 0.9 * rand_unit(),
+            );
+
+            let material: Arc<dyn Material>;
+            let mat_type = rand_unit();
+
+            if mat_type < 0.7 {
+                material = Arc::new(Lambertian::textured(Arc::new(SolidColor {
+                    color: Color::rand() * Color::rand(),
+                })));
+            } else if mat_type < 0.9 {
+                material = Arc::new(Metal::textured(
+                    Arc::new(SolidColor {
+                        color: Color::rand_range(0.5, 1.0),
+                    }),
+                    rand_unit(),
+                ));
+            } else {
+                material = Arc::new(Dielectric::new(rand_unit() + 1.0));
+            }
+
+            world.add(Arc::new(Sphere::new(center, radius, material.clone())));
+        }
+    }
+
+    // Camera
+    let camera_pos = Point3::new(7.5, 1.0, 5.0);
+    let look_at = Point3::new(0.0, 0.0, -1.0);
+
+    let camera = CameraSettings {
+        position: camera_pos,
+        look_at,
+        up: Point3::new(0.0, 1.0, 0.0),
+        vfov: 30.0,
+        aperture: 0.1,
+        focus_dist: (camera_pos - look_at).length(),
+        shutter_open: 0.0,
+        shutter_close: 0.0,
+    };
+
+    Scene {
+        image,
+        camera,
+        world,
+        lights: HittableList::new(),
+        background: Arc::new(GradientBackground::sky()),
+    }
+}
@@ -0,0 +1,295 @@
+use std::sync::Arc;
+
+use crate::aabb::{surrounding_box, Aabb};
+use crate::hittable::*;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sphere::sphere_uv;
+use crate::vec3::*;
+
+// How close to the surface counts as a hit, and the step used to find
+// normals, in the distance field's own units
+const SURFACE_EPSILON: f64 = 1e-4;
+// Most steps taken along one ray before giving up
+const MAX_STEPS: usize = 512;
+// How far to march through fields with no bounds, like repetitions
+const UNBOUNDED_DISTANCE: f64 = 1000.0;
+
+// Signed distance field, negative inside the surface. Shapes are centered
+// on the origin and combine into trees. Most combinators only give a lower
+// bound on the distance rather than the exact value, which is all sphere
+// tracing needs
+pub enum Sdf {
+    Sphere {
+        radius: f64,
+    },
+    // Half the size along each axis
+    Box {
+        half_extents: Vec3,
+    },
+    // Ring in the xz plane, like the analytic torus
+    Torus {
+        major_radius: f64,
+        minor_radius: f64,
+    },
+    // Segment from a to b with rounded ends
+    Capsule {
+        a: Point3,
+        b: Point3,
+        radius: f64,
+    },
+    // Union blending the two within about k of where they meet
+    SmoothUnion {
+        a: Box<Sdf>,
+        b: Box<Sdf>,
+        k: f64,
+    },
+    // a with b cut out of it
+    Subtraction {
+        a: Box<Sdf>,
+        b: Box<Sdf>,
+    },
+    // Copies every period along each axis, with zero for no repetition
+    Repeat {
+        sdf: Box<Sdf>,
+        period: Vec3,
+    },
+    // Rotates slices about the y axis by rate radians per unit of height.
+    // Built with Sdf::twist, which finds how far the shape reaches from the
+    // axis
+    Twist {
+        sdf: Box<Sdf>,
+        rate: f64,
+        reach: f64,
+    },
+    Translate {
+        sdf: Box<Sdf>,
+        offset: Vec3,
+    },
+}
+
+impl Sdf {
+    // Twists a shape about the y axis by rate radians per unit of height.
+    // Only shapes with bounds can be twisted, since how far they reach from
+    // the axis limits how much the twist stretches them
+    pub fn twist(sdf: Sdf, rate: f64) -> Result<Sdf, String> {
+        let bbox = sdf
+            .bounds()
+            .ok_or_else(|| "can't twist a shape that goes on forever".to_string())?;
+
+        // Any rotation about y stays within the furthest corner
+        let x = bbox.min.x.abs().max(bbox.max.x.abs());
+        let z = bbox.min.z.abs().max(bbox.max.z.abs());
+        Ok(Sdf::Twist {
+            sdf: Box::new(sdf),
+            rate,
+            reach: (x * x + z * z).sqrt(),
+        })
+    }
+
+    pub fn distance(&self, p: &Point3) -> f64 {
+        match self {
+            Sdf::Sphere { radius } => p.length() - radius,
+            Sdf::Box { half_extents } => {
+                let q = Vec3::new(
+                    p.x.abs() - half_extents.x,
+                    p.y.abs() - half_extents.y,
+                    p.z.abs() - half_extents.z,
+                );
+                let outside = Vec3::new(q.x.max(0.0), q.y.max(0.0), q.z.max(0.0));
+                outside.length() + q.x.max(q.y).max(q.z).min(0.0)
+            }
+            Sdf::Torus {
+                major_radius,
+                minor_radius,
+            } => {
+                let ring = (p.x * p.x + p.z * p.z).sqrt() - major_radius;
+                (ring * ring + p.y * p.y).sqrt() - minor_radius
+            }
+            Sdf::Capsule { a, b, radius } => {
+                let (pa, ba) = (*p - *a, *b - *a);
+                let h = (dot(&pa, &ba) / ba.length_sq()).clamp(0.0, 1.0);
+                (pa - ba * h).length() - radius
+            }
+            Sdf::SmoothUnion { a, b, k } => {
+                let (da, db) = (a.distance(p), b.distance(p));
+                let h = (0.5 + 0.5 * (db - da) / k).clamp(0.0, 1.0);
+                db * (1.0 - h) + da * h - k * h * (1.0 - h)
+            }
+            Sdf::Subtraction { a, b } => a.distance(p).max(-b.distance(p)),
+            Sdf::Repeat { sdf, period } => {
+                let wrap = |x: f64, period: f64| {
+                    if period > 0.0 {
+                        x - period * (x / period).round()
+                    } else {
+                        x
+                    }
+                };
+                sdf.distance(&Point3::new(
+                    wrap(p.x, period.x),
+                    wrap(p.y, period.y),
+                    wrap(p.z, period.z),
+                ))
+            }
+            Sdf::Twist { sdf, rate, reach } => {
+                let (sin, cos) = (-rate * p.y).sin_cos();
+                let q = Point3::new(cos * p.x - sin * p.z, p.y, sin * p.x + cos * p.z);
+                // Twisting stretches space by up to the largest singular
+                // value of its Jacobian, which grows with the distance from
+                // the axis. A step from p towards the surface stays within
+                // the further of p and the shape's reach, so shrinking
+                // distances by the stretch there keeps it safe
+                let radius = (p.x * p.x + p.z * p.z).sqrt().max(*reach);
+                let stretch = rate.abs() * radius;
+                sdf.distance(&q) / (0.5 * (stretch + (stretch * stretch + 4.0).sqrt()))
+            }
+            Sdf::Translate { sdf, offset } => sdf.distance(&(*p - *offset)),
+        }
+    }
+
+    // Box around the surface, or None when it goes on forever
+    pub fn bounds(&self) -> Option<Aabb> {
+        let centered = |half: Vec3| Some(Aabb::new(-half, half));
+        match self {
+            Sdf::Sphere { radius } => centered(Vec3::new(*radius, *radius, *radius)),
+            Sdf::Box { half_extents } => centered(*half_extents),
+            Sdf::Torus {
+                major_radius,
+                minor_radius,
+            } => {
+                let extent = major_radius + minor_radius;
+                centered(Vec3::new(extent, *minor_radius, extent))
+            }
+            Sdf::Capsule { a, b, radius } => {
+                let r = Vec3::new(*radius, *radius, *radius);
+                Some(surrounding_box(
+                    &Aabb::new(*a - r, *a + r),
+                    &Aabb::new(*b - r, *b + r),
+                ))
+            }
+            Sdf::SmoothUnion { a, b, k } => {
+                // Blending only ever adds material near where they meet
+                let bbox = surrounding_box(&a.bounds()?, &b.bounds()?);
+                let pad = Vec3::new(*k, *k, *k);
+                Some(Aabb::new(bbox.min - pad, bbox.max + pad))
+            }
+            Sdf::Subtraction { a, .. } => a.bounds(),
+            Sdf::Repeat { sdf, period } => {
+                if period.x > 0.0 || period.y > 0.0 || period.z > 0.0 {
+                    None
+                } else {
+                    sdf.bounds()
+                }
+            }
+            Sdf::Twist { sdf, reach, .. } => {
+                let bbox = sdf.bounds()?;
+                Some(Aabb::new(
+                    Point3::new(-reach, bbox.min.y, -reach),
+                    Point3::new(*reach, bbox.max.y, *reach),
+                ))
+            }
+            Sdf::Translate { sdf, offset } => {
+                let bbox = sdf.bounds()?;
+                Some(Aabb::new(bbox.min + *offset, bbox.max + *offset))
+            }
+        }
+    }
+}
+
+// Surface of a signed distance field, found by sphere tracing: step along
+// the ray by the distance to the nearest surface until it's close enough
+// to count as a hit. Normals come from the field's gradient and uvs from
+// the direction of the hit point from the origin
+pub struct SdfObject {
+    sdf: Sdf,
+    mat: Arc<dyn Material>,
+    bbox: Option<Aabb>,
+}
+
+impl SdfObject {
+    pub fn new(sdf: Sdf, mat: Arc<dyn Material>) -> SdfObject {
+        let bbox = sdf.bounds().map(|bbox| {
+            // Hits are allowed a little way off the surface
+            let pad = Vec3::new(SURFACE_EPSILON, SURFACE_EPSILON, SURFACE_EPSILON) * 2.0;
+            Aabb::new(bbox.min - pad, bbox.max + pad)
+        });
+        SdfObject { sdf, mat, bbox }
+    }
+
+    // Central differences of the field, pointing away from the inside
+    fn normal(&self, p: &Point3) -> Vec3 {
+        let h = SURFACE_EPSILON;
+        let axis =
+            |offset: Vec3| self.sdf.distance(&(*p + offset)) - self.sdf.distance(&(*p - offset));
+        let gradient = Vec3::new(
+            axis(Vec3::new(h, 0.0, 0.0)),
+            axis(Vec3::new(0.0, h, 0.0)),
+            axis(Vec3::new(0.0, 0.0, h)),
+        );
+        if gradient.near_zero() {
+            return Vec3::new(0.0, 1.0, 0.0);
+        }
+        normalized(gradient)
+    }
+}
+
+impl Hittable for SdfObject {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let (t_start, t_end) = match self.bbox {
+            Some(bbox) => match bbox.interval(r, t_min, t_max) {
+                Some(interval) => interval,
+                None => return false,
+            },
+            None => (t_min, t_max.min(UNBOUNDED_DISTANCE / r.dir.length())),
+        };
+
+        // Distances are along the field's space, while t is in units of
+        // the ray's direction
+        let length = r.dir.length();
+        let mut t = t_start;
+
+        // Rays leaving the surface, like reflections from a hit on it,
+        // start within the hit distance. Step clear of the surface first
+        // so they don't hit it again straight away
+        let mut leaving = self.sdf.distance(&r.at(t)).abs() < SURFACE_EPSILON;
+
+        for _ in 0..MAX_STEPS {
+            if t > t_end {
+                return false;
+            }
+
+            let distance = self.sdf.distance(&r.at(t)).abs();
+            if leaving {
+                if distance < 2.0 * SURFACE_EPSILON {
+                    t += 2.0 * SURFACE_EPSILON / length;
+                    continue;
+                }
+                leaving = false;
+            }
+
+            if distance < SURFACE_EPSILON {
+                let p = r.at(t);
+                let outward_normal = self.normal(&p);
+                *rec = HitRecord::new(p, t, r, &outward_normal, self.mat.clone());
+                if !p.near_zero() {
+                    (rec.u, rec.v) = sphere_uv(&normalized(p));
+                }
+                return true;
+            }
+
+            t += distance / length;
+        }
+
+        false
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        match self.bbox {
+            Some(bbox) => {
+                *output_box = bbox;
+                true
+            }
+            None => false,
+        }
+    }
+}
@@ -1,33 +1,66 @@
 use std::sync::Arc;
 
+use crate::aabb::{surrounding_box, Aabb};
 use crate::hittable::*;
 use crate::material::Material;
+use crate::math::rand_unit;
+use crate::onb::Onb;
+use crate::ray::Ray;
 use crate::vec3::*;
 
+// The center moves in a straight line from center0 at time0 to center1 at
+// time1, holding still outside that range. A static sphere has both ends
+// the same
 pub struct Sphere {
-    center: Point3,
+    center0: Point3,
+    center1: Point3,
+    time0: f64,
+    time1: f64,
     radius: f64,
     mat: Arc<dyn Material>,
 }
 
 impl Sphere {
     pub fn new(center: Point3, radius: f64, mat: Arc<dyn Material>) -> Sphere {
+        Sphere::moving(center, 0.0, center, 1.0, radius, mat)
+    }
+
+    pub fn moving(
+        center0: Point3,
+        time0: f64,
+        center1: Point3,
+        time1: f64,
+        radius: f64,
+        mat: Arc<dyn Material>,
+    ) -> Sphere {
         Sphere {
-            center,
+            center0,
+            center1,
+            time0,
+            time1,
             radius,
             mat,
         }
     }
+
+    pub fn center(&self, time: f64) -> Point3 {
+        if self.time1 <= self.time0 {
+            return self.center0;
+        }
+        let t = ((time - self.time0) / (self.time1 - self.time0)).clamp(0.0, 1.0);
+        self.center0 + (self.center1 - self.center0) * t
+    }
 }
 
 impl Hittable for Sphere {
-    fn hit(&self, r: &crate::ray::Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
         // Solving for parameters t such that r(t) is on the sphere,
         // i.e. r(t) has distance radius^2 from center
         // i.e. (r(t) - center) \cdot (r(t) - center) = radius^2
         // Below comes from above equation to solve for t w quadratic formula
         // Replacing b with h where b = 2h allows symbolic simplification
-        let oc = r.origin - self.center;
+        let center = self.center(r.time);
+        let oc = r.origin - center;
         let a = r.dir.length_sq();
         let h = dot(&oc, &r.dir);
         let c = oc.length_sq() - self.radius * self.radius;
@@ -48,9 +81,71 @@ impl Hittable for Sphere {
         }
 
         let p = r.at(root);
-        let out_norm = (p - self.center) / self.radius;
+        let out_norm = (p - center) / self.radius;
         *rec = HitRecord::new(p, root, r, &out_norm, self.mat.clone());
+        (rec.u, rec.v) = sphere_uv(&((p - center) / self.radius.abs()));
+
+        true
+    }
 
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        let r = Vec3::new(self.radius.abs(), self.radius.abs(), self.radius.abs());
+        let box0 = Aabb::new(self.center0 - r, self.center0 + r);
+        let box1 = Aabb::new(self.center1 - r, self.center1 + r);
+        *output_box = surrounding_box(&box0, &box1);
         true
     }
+
+    fn pdf_value(&self, origin: &Point3, v: &Vec3) -> f64 {
+        let mut rec = HitRecord::blank();
+        if !self.hit(&Ray::new(*origin, *v, 0.0), 0.001, f64::INFINITY, &mut rec) {
+            return 0.0;
+        }
+
+        // Uniform over the cone of directions that see the sphere. Light
+        // sampling has no time, so this is only right for static spheres
+        let dist_sq = (self.center0 - *origin).length_sq();
+        let r_sq = self.radius * self.radius;
+        if dist_sq <= r_sq {
+            return 0.0;
+        }
+        let cos_theta_max = (1.0 - r_sq / dist_sq).sqrt();
+        let solid_angle = 2.0 * std::f64::consts::PI * (1.0 - cos_theta_max);
+
+        1.0 / solid_angle
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        let direction = self.center0 - *origin;
+        let dist_sq = direction.length_sq();
+        let r_sq = self.radius * self.radius;
+
+        // Can't see the sphere as a cone from inside it, and pdf_value
+        // reports zero there so any direction will do
+        if dist_sq <= r_sq {
+            return Vec3::rand_unit();
+        }
+
+        // Uniformly sample a direction in the cone around the center
+        let cos_theta_max = (1.0 - r_sq / dist_sq).sqrt();
+        let r1 = rand_unit();
+        let r2 = rand_unit();
+        let z = 1.0 + r2 * (cos_theta_max - 1.0);
+        let phi = 2.0 * std::f64::consts::PI * r1;
+        let sin_theta = (1.0 - z * z).sqrt();
+
+        Onb::from_w(&direction).local(&Vec3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, z))
+    }
+}
+
+// Maps a point on the unit sphere to longitude u and latitude v, both in
+// [0, 1], with v = 0 at the bottom (-y) and u starting from -x
+pub fn sphere_uv(p: &Point3) -> (f64, f64) {
+    let theta = (-p.y).clamp(-1.0, 1.0).acos();
+    let phi = (-p.z).atan2(p.x) + std::f64::consts::PI;
+
+    (
+        phi / (2.0 * std::f64::consts::PI),
+        theta / std::f64::consts::PI,
+    )
 }
@@ -0,0 +1,281 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::noise::{Perlin, Worley};
+use crate::vec3::*;
+
+// Color that varies over a surface, looked up by the hit's uv coordinates
+// and/or its position in space
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color;
+}
+
+pub struct SolidColor {
+    pub color: Color,
+}
+
+impl SolidColor {
+    pub fn new(r: f64, g: f64, b: f64) -> SolidColor {
+        SolidColor {
+            color: Color::new(r, g, b),
+        }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+        self.color
+    }
+}
+
+// 3D checkerboard of cubes with sides of length scale, so it doesn't depend
+// on an object's uv mapping
+pub struct CheckerTexture {
+    pub even: Arc<dyn Texture>,
+    pub odd: Arc<dyn Texture>,
+    pub scale: f64,
+}
+
+impl CheckerTexture {
+    pub fn new(even: Color, odd: Color, scale: f64) -> CheckerTexture {
+        CheckerTexture {
+            even: Arc::new(SolidColor { color: even }),
+            odd: Arc::new(SolidColor { color: odd }),
+            scale,
+        }
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let cell =
+            (p.x / self.scale).floor() + (p.y / self.scale).floor() + (p.z / self.scale).floor();
+
+        if cell.rem_euclid(2.0) == 0.0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+// Bitmap wrapped over an object's uv coordinates, repeating outside [0, 1].
+// v = 0 is the bottom row of the image
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    // Linear colors, rows top to bottom
+    pixels: Vec<Color>,
+}
+
+impl ImageTexture {
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>) -> Result<ImageTexture, String> {
+        if width == 0 || height == 0 {
+            return Err("image has no pixels".to_string());
+        }
+        if pixels.len() != width * height {
+            return Err(format!(
+                "expected {} pixels for a {}x{} image, found {}",
+                width * height,
+                width,
+                height,
+                pixels.len()
+            ));
+        }
+
+        Ok(ImageTexture {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    // Loads a PNG or JPEG. 8 and 16 bit images are stored gamma corrected,
+    // which is undone with the same gamma of 2 used for output
+    pub fn load(path: &Path) -> Result<ImageTexture, String> {
+        let image = image::open(path)
+            .map_err(|why| format!("could not read {}: {}", path.display(), why))?
+            .to_rgb32f();
+
+        let pixels = image
+            .pixels()
+            .map(|p| {
+                let [r, g, b] = p.0.map(|c| c as f64);
+                Color::new(r * r, g * g, b * b)
+            })
+            .collect();
+
+        ImageTexture::new(image.width() as usize, image.height() as usize, pixels)
+            .map_err(|why| format!("{}: {}", path.display(), why))
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: &Point3) -> Color {
+        let wrap = |t: f64| {
+            if (0.0..=1.0).contains(&t) {
+                t
+            } else {
+                t.rem_euclid(1.0)
+            }
+        };
+        let u = wrap(u);
+        let v = 1.0 - wrap(v);
+
+        let x = ((u * self.width as f64) as usize).min(self.width - 1);
+        let y = ((v * self.height as f64) as usize).min(self.height - 1);
+
+        self.pixels[y * self.width + x]
+    }
+}
+
+// Checkerboard over an object's uv coordinates, with squares of side scale.
+// Suits flat objects better than the 3D checker, which flickers on
+// surfaces lying right along a cube boundary like the plane y = 0
+pub struct UvCheckerTexture {
+    pub even: Arc<dyn Texture>,
+    pub odd: Arc<dyn Texture>,
+    pub scale: f64,
+}
+
+impl UvCheckerTexture {
+    pub fn new(even: Color, odd: Color, scale: f64) -> UvCheckerTexture {
+        UvCheckerTexture {
+            even: Arc::new(SolidColor { color: even }),
+            odd: Arc::new(SolidColor { color: odd }),
+            scale,
+        }
+    }
+}
+
+impl Texture for UvCheckerTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let cell = (u / self.scale).floor() + (v / self.scale).floor();
+
+        if cell.rem_euclid(2.0) == 0.0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+fn lerp(low: Color, high: Color, t: f64) -> Color {
+    low * (1.0 - t) + high * t
+}
+
+// How a fractal sum of Perlin noise is built up, see Perlin::fbm
+#[derive(Clone, Copy)]
+pub struct Octaves {
+    pub count: u32,
+    pub lacunarity: f64,
+    pub gain: f64,
+}
+
+#[derive(Clone, Copy)]
+pub enum NoisePattern {
+    // A single octave
+    Plain,
+    Fbm,
+    Turbulence,
+}
+
+// Perlin noise blended between two colors. Scale is the feature frequency,
+// so larger values give finer detail
+pub struct NoiseTexture {
+    pub noise: Perlin,
+    pub pattern: NoisePattern,
+    pub scale: f64,
+    pub octaves: Octaves,
+    pub low: Color,
+    pub high: Color,
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f64, _v: f64, p: &Point3) -> Color {
+        let p = *p * self.scale;
+        let o = self.octaves;
+
+        let t = match self.pattern {
+            NoisePattern::Plain => 0.5 * (1.0 + self.noise.noise(&p)),
+            NoisePattern::Fbm => 0.5 * (1.0 + self.noise.fbm(&p, o.count, o.lacunarity, o.gain)),
+            NoisePattern::Turbulence => self.noise.turbulence(&p, o.count, o.lacunarity, o.gain),
+        };
+
+        lerp(self.low, self.high, t.clamp(0.0, 1.0))
+    }
+}
+
+// Bands of veins along x, bent by turbulence. Scale sets how closely the
+// veins are packed while the turbulence keeps its unit feature size
+pub struct MarbleTexture {
+    pub noise: Perlin,
+    pub scale: f64,
+    pub octaves: Octaves,
+    // How far the veins get pushed around
+    pub turbulence: f64,
+    pub low: Color,
+    pub high: Color,
+}
+
+impl Texture for MarbleTexture {
+    fn value(&self, _u: f64, _v: f64, p: &Point3) -> Color {
+        let o = self.octaves;
+        let turb = self.noise.turbulence(p, o.count, o.lacunarity, o.gain);
+        let t = 0.5 * (1.0 + (self.scale * p.x + self.turbulence * turb).sin());
+
+        lerp(self.low, self.high, t)
+    }
+}
+
+// Growth rings around the y axis, scale of them per unit, wobbled by
+// unit sized turbulence
+pub struct WoodTexture {
+    pub noise: Perlin,
+    pub scale: f64,
+    pub octaves: Octaves,
+    pub turbulence: f64,
+    pub low: Color,
+    pub high: Color,
+}
+
+impl Texture for WoodTexture {
+    fn value(&self, _u: f64, _v: f64, p: &Point3) -> Color {
+        let o = self.octaves;
+        let turb = self.noise.turbulence(p, o.count, o.lacunarity, o.gain);
+        let rings = self.scale * (p.x * p.x + p.z * p.z).sqrt() + self.turbulence * turb;
+
+        lerp(self.low, self.high, rings.rem_euclid(1.0))
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum WorleyFeature {
+    // Distance to the nearest point, round cells
+    F1,
+    // Distance to the second nearest point
+    F2,
+    // Difference of the two, dark lines along cell borders
+    F2MinusF1,
+}
+
+pub struct WorleyTexture {
+    pub noise: Worley,
+    pub feature: WorleyFeature,
+    pub scale: f64,
+    pub low: Color,
+    pub high: Color,
+}
+
+impl Texture for WorleyTexture {
+    fn value(&self, _u: f64, _v: f64, p: &Point3) -> Color {
+        let (f1, f2) = self.noise.distances(&(*p * self.scale));
+        let t = match self.feature {
+            WorleyFeature::F1 => f1,
+            WorleyFeature::F2 => f2,
+            WorleyFeature::F2MinusF1 => f2 - f1,
+        };
+
+        lerp(self.low, self.high, t.clamp(0.0, 1.0))
+    }
+}
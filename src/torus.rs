@@ -0,0 +1,93 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hittable::*;
+use crate::material::Material;
+use crate::poly::solve_quartic;
+use crate::quadric::azimuth_u;
+use crate::ray::Ray;
+use crate::vec3::*;
+
+// Torus around the y axis, a tube of minor_radius swept around a circle of
+// major_radius in the xz plane. u goes around the y axis like the other
+// shapes of revolution, v goes around the tube starting from its inside
+pub struct Torus {
+    major_radius: f64,
+    minor_radius: f64,
+    mat: Arc<dyn Material>,
+    bbox: Aabb,
+}
+
+impl Torus {
+    pub fn new(major_radius: f64, minor_radius: f64, mat: Arc<dyn Material>) -> Torus {
+        let extent = major_radius + minor_radius;
+        Torus {
+            major_radius,
+            minor_radius,
+            mat,
+            bbox: Aabb::new(
+                Point3::new(-extent, -minor_radius, -extent),
+                Point3::new(extent, minor_radius, extent),
+            ),
+        }
+    }
+}
+
+impl Hittable for Torus {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        // The quartic's coefficients grow with the fourth power of the
+        // distance to the origin, so start the ray where it enters the box
+        // and measure along a unit direction to keep them well scaled
+        let Some((t_start, _)) = self.bbox.interval(r, t_min, t_max) else {
+            return false;
+        };
+        let length = r.dir.length();
+        let d = r.dir / length;
+        let o = r.at(t_start);
+
+        // (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + z^2) for p = o + s d
+        let r_sq = self.major_radius * self.major_radius;
+        let m = o.length_sq();
+        let n = dot(&o, &d);
+        let k = m + r_sq - self.minor_radius * self.minor_radius;
+        let roots = solve_quartic([
+            1.0,
+            4.0 * n,
+            4.0 * n * n + 2.0 * k - 4.0 * r_sq * (d.x * d.x + d.z * d.z),
+            4.0 * n * k - 8.0 * r_sq * (o.x * d.x + o.z * d.z),
+            k * k - 4.0 * r_sq * (o.x * o.x + o.z * o.z),
+        ]);
+
+        let Some(t) = roots
+            .as_slice()
+            .iter()
+            .map(|s| t_start + s / length)
+            .find(|t| *t >= t_min && *t <= t_max)
+        else {
+            return false;
+        };
+
+        // Away from the nearest point on the tube's center circle
+        let p = r.at(t);
+        let ring = Vec3::new(p.x, 0.0, p.z);
+        let ring_dist = ring.length();
+        let center = if ring_dist > 1e-12 {
+            ring * (self.major_radius / ring_dist)
+        } else {
+            Vec3::new(self.major_radius, 0.0, 0.0)
+        };
+        let outward_normal = normalized(p - center);
+
+        *rec = HitRecord::new(p, t, r, &outward_normal, self.mat.clone());
+        rec.u = azimuth_u(&p);
+        rec.v = (p.y.atan2(ring_dist - self.major_radius) + PI) / (2.0 * PI);
+
+        true
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        *output_box = self.bbox;
+        true
+    }
+}
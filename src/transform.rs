@@ -0,0 +1,407 @@
+use std::sync::Arc;
+
+use crate::aabb::{surrounding_box, Aabb};
+use crate::hittable::*;
+use crate::mat4::Mat4;
+use crate::ray::Ray;
+use crate::vec3::*;
+
+// Places an object in the world through an affine transform. The object
+// itself stays in its own space and can be shared between any number of
+// Transformed instances
+pub struct Transformed {
+    object: Arc<dyn Hittable>,
+    // Object to world
+    transform: Mat4,
+    // World to object
+    inverse: Mat4,
+    // Transposed inverse, which keeps normals perpendicular to surfaces
+    normal_transform: Mat4,
+    bbox: Option<Aabb>,
+}
+
+impl Transformed {
+    // None if the transform can't be inverted
+    pub fn new(object: Arc<dyn Hittable>, transform: Mat4) -> Option<Transformed> {
+        let inverse = transform.inverse()?;
+
+        let mut object_box = Aabb::empty();
+        let bbox = if object.bounding_box(&mut object_box) {
+            Some(transformed_box(&object_box, &transform))
+        } else {
+            None
+        };
+
+        Some(Transformed {
+            object,
+            transform,
+            inverse,
+            normal_transform: inverse.transpose(),
+            bbox,
+        })
+    }
+
+    fn matrices(&self) -> [Mat4; 3] {
+        [self.transform, self.inverse, self.normal_transform]
+    }
+}
+
+impl Hittable for Transformed {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        hit_transformed(&self.matrices(), r, rec, |object_ray, rec| {
+            self.object.hit(object_ray, t_min, t_max, rec)
+        })
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        match self.bbox {
+            Some(bbox) => {
+                *output_box = bbox;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn pdf_value(&self, origin: &Point3, v: &Vec3) -> f64 {
+        let local_origin = self.inverse.transform_point(origin);
+        let local_v = self.inverse.transform_vector(v);
+        let pdf = self.object.pdf_value(&local_origin, &local_v);
+        if pdf == 0.0 {
+            return 0.0;
+        }
+
+        // Solid angle scales between the spaces by |det A| / |A w|^3 for
+        // the linear map A and unit direction w
+        let stretch = self.inverse.transform_vector(&normalized(*v)).length();
+        pdf * self.inverse.linear_determinant().abs() / (stretch * stretch * stretch)
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        let local_origin = self.inverse.transform_point(origin);
+        self.transform
+            .transform_vector(&self.object.random(&local_origin))
+    }
+
+    fn shadow_hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        hit_transformed(&self.matrices(), r, rec, |object_ray, rec| {
+            self.object.shadow_hit(object_ray, t_min, t_max, rec)
+        })
+    }
+
+    fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64) -> f64 {
+        self.object
+            .transmittance(&object_ray(&self.inverse, r), t_min, t_max)
+    }
+}
+
+// The direction isn't renormalized, so t means the same in both spaces
+fn object_ray(inverse: &Mat4, r: &Ray) -> Ray {
+    Ray::new(
+        inverse.transform_point(&r.origin),
+        inverse.transform_vector(&r.dir),
+        r.time,
+    )
+}
+
+// Runs hit on the object space ray, then brings the hit back through the
+// given object to world, world to object and normal matrices
+fn hit_transformed(
+    [transform, inverse, normal_transform]: &[Mat4; 3],
+    r: &Ray,
+    rec: &mut HitRecord,
+    hit: impl FnOnce(&Ray, &mut HitRecord) -> bool,
+) -> bool {
+    if !hit(&object_ray(inverse, r), rec) {
+        return false;
+    }
+
+    // The normal already faces against the object space ray, and the
+    // inverse transpose keeps it facing against the world ray
+    rec.p = transform.transform_point(&rec.p);
+    rec.normal = normalized(normal_transform.transform_vector(&rec.normal));
+
+    true
+}
+
+// Box around all 8 transformed corners of an object space box
+fn transformed_box(object_box: &Aabb, transform: &Mat4) -> Aabb {
+    let extremes = [object_box.min, object_box.max];
+    let mut world_box = Aabb::empty();
+    for i in 0..8 {
+        let corner = Point3::new(
+            extremes[i & 1].x,
+            extremes[(i >> 1) & 1].y,
+            extremes[(i >> 2) & 1].z,
+        );
+        world_box = world_box.expand(transform.transform_point(&corner));
+    }
+    world_box
+}
+
+// One piece of a transform, kept separate from the matrix it makes so
+// keyframes can be blended by their parameters
+#[derive(Clone, Copy)]
+pub enum TransformStep {
+    Translate(Vec3),
+    Scale(Vec3),
+    // Axis and angle in radians
+    Rotate(Vec3, f64),
+    Matrix(Mat4),
+}
+
+impl TransformStep {
+    pub fn matrix(&self) -> Mat4 {
+        match self {
+            TransformStep::Translate(offset) => Mat4::translation(offset),
+            TransformStep::Scale(factors) => Mat4::scaling(factors),
+            TransformStep::Rotate(axis, angle) => Mat4::rotation(axis, *angle),
+            TransformStep::Matrix(m) => *m,
+        }
+    }
+
+    // Blends towards other, which has to be the same kind of step. Angles
+    // are interpolated directly so rotations keep their shape, while
+    // matrices are blended entry by entry, which only suits small changes
+    pub fn lerp(&self, other: &TransformStep, t: f64) -> Option<TransformStep> {
+        let mix = |a: Vec3, b: Vec3| a * (1.0 - t) + b * t;
+
+        match (self, other) {
+            (TransformStep::Translate(a), TransformStep::Translate(b)) => {
+                Some(TransformStep::Translate(mix(*a, *b)))
+            }
+            (TransformStep::Scale(a), TransformStep::Scale(b)) => {
+                Some(TransformStep::Scale(mix(*a, *b)))
+            }
+            (TransformStep::Rotate(axis_a, a), TransformStep::Rotate(axis_b, b)) => {
+                let axis = mix(normalized(*axis_a), normalized(*axis_b));
+                let axis = if axis.near_zero() { *axis_a } else { axis };
+                Some(TransformStep::Rotate(axis, a * (1.0 - t) + b * t))
+            }
+            (TransformStep::Matrix(a), TransformStep::Matrix(b)) => {
+                let mut m = a.m;
+                for (row, other_row) in m.iter_mut().zip(b.m.iter()) {
+                    for (x, y) in row.iter_mut().zip(other_row.iter()) {
+                        *x = *x * (1.0 - t) + y * t;
+                    }
+                }
+                Some(TransformStep::Matrix(Mat4::new(m)))
+            }
+            _ => None,
+        }
+    }
+}
+
+// Applies the steps in order
+pub fn compose(steps: &[TransformStep]) -> Mat4 {
+    steps
+        .iter()
+        .fold(Mat4::identity(), |acc, step| step.matrix() * acc)
+}
+
+pub struct Keyframe {
+    pub time: f64,
+    pub steps: Vec<TransformStep>,
+}
+
+// How many transforms are sampled between each pair of keyframes when
+// bounding the motion
+const MOTION_SAMPLES: usize = 32;
+
+// Fastest any point within radius of the origin moves while blending from
+// one keyframe's steps to the next, in distance per whole blend. Infinite
+// when it can't be bounded, like a rotation axis flipping to its opposite
+fn motion_speed(from: &[TransformStep], to: &[TransformStep], radius: f64) -> f64 {
+    // How far from the origin points can be, and how fast they can move,
+    // after each step
+    let (mut reach, mut speed) = (radius, 0.0);
+
+    for (a, b) in from.iter().zip(to.iter()) {
+        match (a, b) {
+            (TransformStep::Translate(a), TransformStep::Translate(b)) => {
+                speed += (*b - *a).length();
+                reach += a.length().max(b.length());
+            }
+            (TransformStep::Scale(a), TransformStep::Scale(b)) => {
+                let largest = |v: Vec3| v.x.abs().max(v.y.abs()).max(v.z.abs());
+                let scale = largest(*a).max(largest(*b));
+                speed = largest(*b - *a) * reach + scale * speed;
+                reach *= scale;
+            }
+            (TransformStep::Rotate(axis_a, angle_a), TransformStep::Rotate(axis_b, angle_b)) => {
+                // Rotations keep distances from the origin. A blended axis
+                // turns fastest halfway, where it's shortest before being
+                // normalized, and moves points by at most 5 times that
+                let (axis_a, axis_b) = (normalized(*axis_a), normalized(*axis_b));
+                let axis_change = (axis_b - axis_a).length();
+                let shortest = (axis_a + axis_b).length() / 2.0;
+                let axis_turn = if axis_change == 0.0 {
+                    0.0
+                } else if shortest < 1e-6 {
+                    return f64::INFINITY;
+                } else {
+                    axis_change / shortest
+                };
+                speed += ((angle_b - angle_a).abs() + 5.0 * axis_turn) * reach;
+            }
+            (TransformStep::Matrix(a), TransformStep::Matrix(b)) => {
+                // Frobenius norms of the linear parts bound how much they
+                // stretch, and are largest at one end of the blend
+                let linear = |f: &dyn Fn(usize, usize) -> f64| {
+                    (0..9).map(|k| f(k / 3, k % 3).powi(2)).sum::<f64>().sqrt()
+                };
+                let offset = |m: &Mat4| Vec3::new(m.m[0][3], m.m[1][3], m.m[2][3]);
+                let stretch = linear(&|i, j| a.m[i][j]).max(linear(&|i, j| b.m[i][j]));
+                let change = linear(&|i, j| b.m[i][j] - a.m[i][j]);
+                speed = change * reach + stretch * speed + (offset(b) - offset(a)).length();
+                reach = stretch * reach + offset(a).length().max(offset(b).length());
+            }
+            _ => return f64::INFINITY,
+        }
+    }
+
+    speed
+}
+
+// An object moving through a series of keyframed transforms. Before the
+// first keyframe and after the last the object holds still
+pub struct Animated {
+    object: Arc<dyn Hittable>,
+    keyframes: Vec<Keyframe>,
+    bbox: Option<Aabb>,
+}
+
+impl Animated {
+    // None unless there are keyframes, in time order with matching steps
+    pub fn new(object: Arc<dyn Hittable>, keyframes: Vec<Keyframe>) -> Option<Animated> {
+        if keyframes.is_empty() {
+            return None;
+        }
+        for pair in keyframes.windows(2) {
+            if pair[1].time < pair[0].time || pair[1].steps.len() != pair[0].steps.len() {
+                return None;
+            }
+            for (a, b) in pair[0].steps.iter().zip(pair[1].steps.iter()) {
+                a.lerp(b, 0.0)?;
+            }
+        }
+
+        let mut animated = Animated {
+            object,
+            keyframes,
+            bbox: None,
+        };
+
+        // Sample the motion densely. Every point of the object is within
+        // half a sample's worth of movement of where it is at the nearest
+        // sample, so padding by that covers the motion in between
+        let mut object_box = Aabb::empty();
+        if animated.object.bounding_box(&mut object_box) {
+            let corner = Vec3::new(
+                object_box.min.x.abs().max(object_box.max.x.abs()),
+                object_box.min.y.abs().max(object_box.max.y.abs()),
+                object_box.min.z.abs().max(object_box.max.z.abs()),
+            );
+            let segments = animated.keyframes.len().max(2) - 1;
+            let mut bbox = Some(Aabb::empty());
+            for segment in 0..segments {
+                let a = &animated.keyframes[segment];
+                let b = animated.keyframes.get(segment + 1).unwrap_or(a);
+                let speed = motion_speed(&a.steps, &b.steps, corner.length());
+                if !speed.is_finite() {
+                    bbox = None;
+                    break;
+                }
+
+                let mut segment_box = Aabb::empty();
+                for i in 0..=MOTION_SAMPLES {
+                    let m = animated.segment_transform(segment, i as f64 / MOTION_SAMPLES as f64);
+                    segment_box = surrounding_box(&segment_box, &transformed_box(&object_box, &m));
+                }
+                let pad = speed / (2 * MOTION_SAMPLES) as f64;
+                let pad = Vec3::new(pad, pad, pad);
+                let segment_box = Aabb::new(segment_box.min - pad, segment_box.max + pad);
+                bbox = bbox.map(|bbox| surrounding_box(&bbox, &segment_box));
+            }
+            animated.bbox = bbox.map(|bbox| bbox.padded());
+        }
+
+        Some(animated)
+    }
+
+    // Transform a fraction t of the way from one keyframe to the next
+    fn segment_transform(&self, segment: usize, t: f64) -> Mat4 {
+        let a = &self.keyframes[segment];
+        let b = self.keyframes.get(segment + 1).unwrap_or(a);
+        let steps: Vec<TransformStep> = a
+            .steps
+            .iter()
+            .zip(b.steps.iter())
+            .map(|(x, y)| x.lerp(y, t).unwrap())
+            .collect();
+        compose(&steps)
+    }
+
+    pub fn transform(&self, time: f64) -> Mat4 {
+        let keyframes = &self.keyframes;
+        let last = keyframes.len() - 1;
+
+        if time <= keyframes[0].time {
+            return self.segment_transform(0, 0.0);
+        }
+        if time >= keyframes[last].time {
+            return self.segment_transform(last, 0.0);
+        }
+
+        let segment = keyframes.partition_point(|k| k.time <= time) - 1;
+        let span = keyframes[segment + 1].time - keyframes[segment].time;
+        self.segment_transform(segment, (time - keyframes[segment].time) / span)
+    }
+
+    // None while the object is scaled down to nothing
+    fn matrices(&self, time: f64) -> Option<[Mat4; 3]> {
+        let transform = self.transform(time);
+        let inverse = transform.inverse()?;
+        Some([transform, inverse, inverse.transpose()])
+    }
+}
+
+impl Hittable for Animated {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        match self.matrices(r.time) {
+            Some(matrices) => hit_transformed(&matrices, r, rec, |object_ray, rec| {
+                self.object.hit(object_ray, t_min, t_max, rec)
+            }),
+            None => false,
+        }
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        match self.bbox {
+            Some(bbox) => {
+                *output_box = bbox;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn shadow_hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        match self.matrices(r.time) {
+            Some(matrices) => hit_transformed(&matrices, r, rec, |object_ray, rec| {
+                self.object.shadow_hit(object_ray, t_min, t_max, rec)
+            }),
+            None => false,
+        }
+    }
+
+    fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64) -> f64 {
+        match self.matrices(r.time) {
+            Some([_, inverse, _]) => {
+                self.object
+                    .transmittance(&object_ray(&inverse, r), t_min, t_max)
+            }
+            None => 1.0,
+        }
+    }
+}
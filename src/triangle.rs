@@ -0,0 +1,130 @@
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hittable::*;
+use crate::material::Material;
+use crate::math::rand_unit;
+use crate::ray::Ray;
+use crate::vec3::*;
+
+pub struct Triangle {
+    v0: Point3,
+    v1: Point3,
+    v2: Point3,
+    mat: Arc<dyn Material>,
+}
+
+impl Triangle {
+    pub fn new(v0: Point3, v1: Point3, v2: Point3, mat: Arc<dyn Material>) -> Triangle {
+        Triangle { v0, v1, v2, mat }
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let (t, b1, b2) = match intersect(r, &self.v0, &self.v1, &self.v2, t_min, t_max) {
+            Some(hit) => hit,
+            None => return false,
+        };
+
+        let out_norm = normalized(cross(&(self.v1 - self.v0), &(self.v2 - self.v0)));
+        *rec = HitRecord::new(r.at(t), t, r, &out_norm, self.mat.clone());
+        rec.u = b1;
+        rec.v = b2;
+        rec.barycentric = (b1, b2);
+
+        true
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        *output_box = triangle_bounds(&self.v0, &self.v1, &self.v2);
+        true
+    }
+
+    fn pdf_value(&self, origin: &Point3, v: &Vec3) -> f64 {
+        triangle_pdf(origin, v, &self.v0, &self.v1, &self.v2)
+    }
+
+    fn random(&self, origin: &Point3) -> Vec3 {
+        random_on_triangle(&self.v0, &self.v1, &self.v2) - *origin
+    }
+}
+
+// Möller–Trumbore ray/triangle intersection. Returns the ray parameter and
+// the barycentric weights of v1 and v2 at the hit point
+pub fn intersect(
+    r: &Ray,
+    v0: &Point3,
+    v1: &Point3,
+    v2: &Point3,
+    t_min: f64,
+    t_max: f64,
+) -> Option<(f64, f64, f64)> {
+    let edge1 = *v1 - *v0;
+    let edge2 = *v2 - *v0;
+    let pvec = cross(&r.dir, &edge2);
+    let det = dot(&edge1, &pvec);
+
+    // Ray is parallel to the triangle's plane
+    if det.abs() < 1e-12 {
+        return None;
+    }
+
+    // Solve for the barycentrics with Cramer's rule, bailing out as soon as
+    // one falls outside the triangle
+    let inv_det = 1.0 / det;
+    let tvec = r.origin - *v0;
+    let b1 = dot(&tvec, &pvec) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+
+    let qvec = cross(&tvec, &edge1);
+    let b2 = dot(&r.dir, &qvec) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+
+    let t = dot(&edge2, &qvec) * inv_det;
+    if t < t_min || t > t_max {
+        return None;
+    }
+
+    Some((t, b1, b2))
+}
+
+pub fn triangle_bounds(v0: &Point3, v1: &Point3, v2: &Point3) -> Aabb {
+    Aabb::empty().expand(*v0).expand(*v1).expand(*v2).padded()
+}
+
+// Solid angle density of sampling direction v from origin by picking points
+// uniformly over the triangle's area
+pub fn triangle_pdf(origin: &Point3, v: &Vec3, v0: &Point3, v1: &Point3, v2: &Point3) -> f64 {
+    let r = Ray::new(*origin, *v, 0.0);
+    let t = match intersect(&r, v0, v1, v2, 0.001, f64::INFINITY) {
+        Some((t, _, _)) => t,
+        None => return 0.0,
+    };
+
+    let n = cross(&(*v1 - *v0), &(*v2 - *v0));
+    let area = 0.5 * n.length();
+    let dist_sq = t * t * v.length_sq();
+    let cosine = (dot(v, &n) / (v.length() * n.length())).abs();
+    if cosine < 1e-8 {
+        return 0.0;
+    }
+
+    dist_sq / (cosine * area)
+}
+
+pub fn random_on_triangle(v0: &Point3, v1: &Point3, v2: &Point3) -> Point3 {
+    // Folding the unit square in half gives uniform barycentrics
+    let mut b1 = rand_unit();
+    let mut b2 = rand_unit();
+    if b1 + b2 > 1.0 {
+        b1 = 1.0 - b1;
+        b2 = 1.0 - b2;
+    }
+
+    *v0 + (*v1 - *v0) * b1 + (*v2 - *v0) * b2
+}
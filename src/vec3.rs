@@ -52,6 +52,16 @@ impl Vec3 {
         normalized(Vec3::rand_in_sphere())
     }
 
+    // Direction in the +z hemisphere, distributed with density cos(theta) / pi
+    pub fn rand_cosine_direction() -> Vec3 {
+        let r1 = rand_unit();
+        let r2 = rand_unit();
+        let phi = 2.0 * std::f64::consts::PI * r1;
+        let r = r2.sqrt();
+
+        Vec3::new(phi.cos() * r, phi.sin() * r, (1.0 - r2).sqrt())
+    }
+
     pub fn rand_in_disk() -> Vec3 {
         loop {
             let p = Vec3::new(rand_range(-1.0, 1.0), rand_range(-1.0, 1.0), 0.0);
@@ -0,0 +1,234 @@
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hittable::*;
+use crate::material::Material;
+use crate::math::rand_unit;
+use crate::medium::medium_hit;
+use crate::ray::Ray;
+use crate::vec3::*;
+
+// Magic bytes at the start of a voxel grid file
+const GRID_MAGIC: &[u8; 4] = b"VGRD";
+
+// Dense grid of density samples, one per voxel. Stored on disk as:
+//
+//     4 bytes   "VGRD"
+//     3 x u32   nx, ny, nz, little endian
+//     f32 x n   n = nx * ny * nz densities, little endian, with x varying
+//               fastest, then y, then z
+//
+// Anything a simulation can dump as a raw float array only needs the 16
+// byte header put in front of it
+pub struct VoxelGrid {
+    pub nx: usize,
+    pub ny: usize,
+    pub nz: usize,
+    pub densities: Vec<f32>,
+    // Largest density anywhere in the grid
+    max: f64,
+}
+
+impl VoxelGrid {
+    pub fn new(nx: usize, ny: usize, nz: usize, densities: Vec<f32>) -> Result<VoxelGrid, String> {
+        if nx == 0 || ny == 0 || nz == 0 {
+            return Err("grid dimensions must be positive".to_string());
+        }
+        let count = nx.checked_mul(ny).and_then(|n| n.checked_mul(nz));
+        if count != Some(densities.len()) {
+            return Err(format!(
+                "expected one density per voxel of a {}x{}x{} grid, found {}",
+                nx,
+                ny,
+                nz,
+                densities.len()
+            ));
+        }
+        if densities.iter().any(|d| !d.is_finite() || *d < 0.0) {
+            return Err("densities must be finite and not negative".to_string());
+        }
+
+        let max = densities.iter().fold(0.0f32, |a, &b| a.max(b)) as f64;
+        Ok(VoxelGrid {
+            nx,
+            ny,
+            nz,
+            densities,
+            max,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<VoxelGrid, String> {
+        let bytes =
+            fs::read(path).map_err(|why| format!("could not read {}: {}", path.display(), why))?;
+        let error = |message: &str| format!("{}: {}", path.display(), message);
+
+        if bytes.len() < 16 || &bytes[0..4] != GRID_MAGIC {
+            return Err(error("not a voxel grid, missing the VGRD header"));
+        }
+        let dim = |i: usize| {
+            u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]) as usize
+        };
+        let (nx, ny, nz) = (dim(4), dim(8), dim(12));
+        if nx == 0 || ny == 0 || nz == 0 {
+            return Err(error("grid dimensions must be positive"));
+        }
+
+        let count = nx
+            .checked_mul(ny)
+            .and_then(|n| n.checked_mul(nz))
+            .ok_or_else(|| error("grid dimensions are too large"))?;
+        let data_len = count
+            .checked_mul(4)
+            .ok_or_else(|| error("grid dimensions are too large"))?;
+        if bytes.len() - 16 != data_len {
+            return Err(error(&format!(
+                "expected {} densities for a {}x{}x{} grid, found {} bytes of data",
+                count,
+                nx,
+                ny,
+                nz,
+                bytes.len() - 16
+            )));
+        }
+
+        let densities: Vec<f32> = bytes[16..]
+            .chunks_exact(4)
+            .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+            .collect();
+        VoxelGrid::new(nx, ny, nz, densities).map_err(|why| error(&why))
+    }
+
+    fn voxel(&self, x: usize, y: usize, z: usize) -> f64 {
+        self.densities[(z * self.ny + y) * self.nx + x] as f64
+    }
+
+    // Trilinearly interpolated density at p in [0, 1]^3, with voxel centers
+    // at the middle of each cell
+    pub fn density(&self, p: &Point3) -> f64 {
+        // Index of the lower neighbour and the weight of the upper one
+        let axis = |t: f64, n: usize| {
+            let g = (t * n as f64 - 0.5).clamp(0.0, (n - 1) as f64);
+            let i = (g as usize).min(n.saturating_sub(2));
+            (i, (i + 1).min(n - 1), g - i as f64)
+        };
+        let (x0, x1, fx) = axis(p.x, self.nx);
+        let (y0, y1, fy) = axis(p.y, self.ny);
+        let (z0, z1, fz) = axis(p.z, self.nz);
+
+        let lerp = |a: f64, b: f64, t: f64| a * (1.0 - t) + b * t;
+        let plane = |z| {
+            lerp(
+                lerp(self.voxel(x0, y0, z), self.voxel(x1, y0, z), fx),
+                lerp(self.voxel(x0, y1, z), self.voxel(x1, y1, z), fx),
+                fy,
+            )
+        };
+
+        lerp(plane(z0), plane(z1), fz)
+    }
+}
+
+// Volume whose density varies over a voxel grid. The grid fills a box
+// centered on the origin with its longest side 1 unit long, to be placed
+// with a transform. Densities are multiplied by scale and are per unit of
+// the grid's own space, so scaling a volume up keeps how opaque it looks
+pub struct GridMedium {
+    grid: Arc<VoxelGrid>,
+    scale: f64,
+    phase_function: Arc<dyn Material>,
+    bbox: Aabb,
+}
+
+impl GridMedium {
+    pub fn new(grid: Arc<VoxelGrid>, scale: f64, phase_function: Arc<dyn Material>) -> GridMedium {
+        let longest = grid.nx.max(grid.ny).max(grid.nz) as f64;
+        let half = Vec3::new(grid.nx as f64, grid.ny as f64, grid.nz as f64) / (2.0 * longest);
+
+        GridMedium {
+            grid,
+            scale,
+            phase_function,
+            bbox: Aabb::new(-half, half),
+        }
+    }
+
+    fn density(&self, p: &Point3) -> f64 {
+        let size = self.bbox.max - self.bbox.min;
+        let local = *p - self.bbox.min;
+        self.scale
+            * self.grid.density(&Point3::new(
+                local.x / size.x,
+                local.y / size.y,
+                local.z / size.z,
+            ))
+    }
+
+    // Tentative collisions along the ray through the box, as if the whole
+    // grid had the largest density. Calls visit with the ratio of the real
+    // density at each to that largest one, stopping when visit returns
+    // false. Returns the ray parameter it stopped at
+    fn track(
+        &self,
+        r: &Ray,
+        t_min: f64,
+        t_max: f64,
+        mut visit: impl FnMut(f64) -> bool,
+    ) -> Option<f64> {
+        let max_density = self.scale * self.grid.max;
+        if max_density <= 0.0 {
+            return None;
+        }
+        let (t_enter, t_exit) = self.bbox.interval(r, t_min, t_max)?;
+
+        let ray_length = r.dir.length();
+        let mut t = t_enter;
+        loop {
+            t -= (1.0 - rand_unit()).ln() / (max_density * ray_length);
+            if t >= t_exit {
+                return None;
+            }
+            if !visit(self.density(&r.at(t)) / max_density) {
+                return Some(t);
+            }
+        }
+    }
+}
+
+impl Hittable for GridMedium {
+    // Delta tracking. Each tentative collision is a real one with
+    // probability of the density ratio, otherwise it's a null collision
+    // and the ray carries on unchanged
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        match self.track(r, t_min, t_max, |ratio| rand_unit() >= ratio) {
+            Some(t) => {
+                *rec = medium_hit(r, t, self.phase_function.clone());
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn bounding_box(&self, output_box: &mut Aabb) -> bool {
+        *output_box = self.bbox;
+        true
+    }
+
+    fn shadow_hit(&self, _r: &Ray, _t_min: f64, _t_max: f64, _rec: &mut HitRecord) -> bool {
+        false
+    }
+
+    // Ratio tracking. Rather than ending at a random real collision, every
+    // tentative one scales the transmittance by its chance of being null,
+    // which gives a smooth estimate instead of all or nothing
+    fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64) -> f64 {
+        let mut transmittance = 1.0;
+        self.track(r, t_min, t_max, |ratio| {
+            transmittance *= 1.0 - ratio;
+            transmittance > 0.0
+        });
+        transmittance
+    }
+}